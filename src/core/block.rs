@@ -1,129 +1,319 @@
 use super::block_header::BlockHeader;
-use crate::utils::hash::{bytes_to_hex_string, hex_string_to_bytes};
+use super::merkle::{MerkleProof, merkle_root};
+use super::miner::{CancelToken, Miner, MiningOutcome};
+use super::target::{U256, meets_target, target_from_compact, work_from_target};
+use super::transaction::Transaction;
+use super::validation::InvalidBlock;
+use crate::error::Error;
+use crate::utils::hash::Hash256;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Block {
     pub header: BlockHeader,
-    pub transactions: Vec<String>,
-    pub hash: Vec<u8>,
+    pub transactions: Vec<Transaction>,
+    pub hash: Hash256,
+    pub seal: Vec<u8>,
 }
 
 /// Represents a block in the blockchain.
 ///
-/// The `Block` struct contains a header, a list of transactions, and a hash.
+/// The `Block` struct contains a header, a list of transactions, and a hash. The Merkle root in
+/// the header is built from the transaction IDs. `seal` holds whatever the chain's consensus
+/// engine adds on top of the hash, such as the signature of a proof-of-authority block; it is
+/// empty for proof of work.
 /// It provides methods to create a new block, calculate its hash, and mine it.
 ///
 /// # Methods
 ///
-/// - `new(prev_hash_hex: String, transactions: Vec<String>, difficulty: u32) -> Self`
-///   Creates a new block with the given previous hash, transactions, and mining difficulty.
+/// - `new(prev_hash: Hash256, transactions: Vec<Transaction>, bits: u32) -> Result<Self, Error>`
+///   Creates an unmined block with the given previous hash, transactions, and compact target.
+///   Its `hash` is the hash of the header with nonce zero, which usually does not meet the
+///   target yet. Fails if the clock is before the epoch.
 ///
-/// - `calculate_hash(&self) -> Vec<u8>`
-///   Calculates the hash of the block based on its header and transactions.
+/// - `calculate_hash(&self) -> Hash256`
+///   Calculates the hash of the block as the SHA-256 of the canonical header encoding, which
+///   commits to the transactions through the Merkle root.
 ///
-/// - `mine(&mut self)`
-///   Mines the block by finding a valid hash that meets the specified difficulty.
+/// - `merkle_proof(&self, index: usize) -> Option<MerkleProof>`
+///   Produces an inclusion proof for the transaction at `index`, verifiable against
+///   `header.merkle_root` with `MerkleProof::verify`.
+///
+/// - `mine(self, miner: &Miner) -> Result<Self, Error>`
+///   Mines the block until its hash is at or below the header's target, without cancellation
+///   or progress reports; see `Miner::mine` for both. Fails if `bits` is not a valid compact
+///   target.
+///
+/// - `verify(&self) -> Result<(), InvalidBlock>`
+///   Checks the Merkle root, the transaction signatures, and that the stored hash matches the
+///   header. The seal is left to the consensus engine.
+///
+/// - `check_work(&self) -> Result<(), InvalidBlock>`
+///   Checks that the hash is at or below the target, the seal of a proof-of-work block.
+///
+/// - `work(&self) -> U256`
+///   Returns the expected number of hashes needed to mine a block with this block's target.
 impl Block {
-    pub fn new(prev_hash_hex: String, transactions: Vec<String>, difficulty: u32) -> Self {
-        let prev_hash = hex_string_to_bytes(&prev_hash_hex);
-        let header = BlockHeader::new(prev_hash, difficulty);
-        let mut block = Self {
+    pub fn new(
+        prev_hash: Hash256,
+        transactions: Vec<Transaction>,
+        bits: u32,
+    ) -> Result<Self, Error> {
+        let merkle_root = Self::compute_merkle_root(&transactions);
+        let header = BlockHeader::new(prev_hash, merkle_root, bits)?;
+        let hash = header.hash();
+        Ok(Self {
             header,
             transactions,
-            hash: vec![],
-        };
-        block.mine();
-        block
-    }
-
-    fn calculate_hash(&self) -> Vec<u8> {
-        let mut hasher = Sha256::new();
-        let prev_hash_hex = bytes_to_hex_string(&self.header.prev_hash);
-        let data = format!(
-            "{}{}{}{}",
-            self.header.timestamp,
-            prev_hash_hex,
-            self.header.nonce,
-            self.transactions.join("")
-        );
-        hasher.update(data.as_bytes());
-        hasher.finalize().to_vec()
-    }
-
-    fn mine(&mut self) {
-        let target_prefix = vec![0u8; (self.header.difficulty / 8) as usize];
-        let remaining_bits = self.header.difficulty % 8;
-        let last_byte_mask = if remaining_bits > 0 {
-            0xFF >> remaining_bits
-        } else {
-            0
-        };
-
-        loop {
-            self.hash = self.calculate_hash();
-
-            let mut matches = self.hash.starts_with(&target_prefix);
-            if matches && remaining_bits > 0 {
-                matches = self.hash[target_prefix.len()] <= last_byte_mask;
-            }
-
-            if matches {
-                break;
-            }
-            self.header.nonce += 1;
+            hash,
+            seal: Vec::new(),
+        })
+    }
+
+    fn calculate_hash(&self) -> Hash256 {
+        self.header.hash()
+    }
+
+    pub fn mine(self, miner: &Miner) -> Result<Self, Error> {
+        match miner.mine(&self, &CancelToken::new(), |_| {})? {
+            MiningOutcome::Mined { block, .. } => Ok(block),
+            MiningOutcome::Cancelled { .. } => unreachable!("the cancel token is never cancelled"),
+        }
+    }
+
+    fn transaction_hashes(transactions: &[Transaction]) -> Vec<Vec<u8>> {
+        transactions.iter().map(Transaction::id).collect()
+    }
+
+    pub fn compute_merkle_root(transactions: &[Transaction]) -> Hash256 {
+        // Every leaf is a SHA-256 transaction ID, so the root is always 32 bytes long.
+        Hash256::from_slice(&merkle_root(&Self::transaction_hashes(transactions)))
+            .expect("transaction IDs are 32 bytes")
+    }
+
+    /// Returns the index of the first transaction whose signature does not verify.
+    pub fn invalid_signature_index(&self) -> Option<usize> {
+        self.transactions
+            .iter()
+            .position(|tx| !tx.has_valid_signature())
+    }
+
+    pub fn invalid_recipient_index(&self) -> Option<usize> {
+        self.transactions
+            .iter()
+            .position(|tx| !tx.has_valid_recipients())
+    }
+
+    pub fn merkle_proof(&self, index: usize) -> Option<MerkleProof> {
+        MerkleProof::build(&Self::transaction_hashes(&self.transactions), index)
+    }
+
+    pub fn verify(&self) -> Result<(), InvalidBlock> {
+        if self.header.version != BlockHeader::VERSION {
+            return Err(InvalidBlock::UnsupportedVersion(self.header.version));
+        }
+        if Self::compute_merkle_root(&self.transactions) != self.header.merkle_root {
+            return Err(InvalidBlock::MerkleRootMismatch);
+        }
+        if let Some(index) = self.invalid_signature_index() {
+            return Err(InvalidBlock::InvalidSignature { index });
         }
+        if let Some(index) = self.invalid_recipient_index() {
+            return Err(InvalidBlock::InvalidRecipient { index });
+        }
+        if self.calculate_hash() != self.hash {
+            return Err(InvalidBlock::HashMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn check_work(&self) -> Result<(), InvalidBlock> {
+        let target = target_from_compact(self.header.bits).ok_or(InvalidBlock::InvalidTarget)?;
+        if !meets_target(self.hash.as_bytes(), target) {
+            return Err(InvalidBlock::InsufficientWork);
+        }
+        Ok(())
+    }
 
-        println!("Block mined successfully. Hash: {}", bytes_to_hex_string(&self.hash));
+    pub fn work(&self) -> U256 {
+        target_from_compact(self.header.bits).map_or(U256::ZERO, work_from_target)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::target::compact_from_difficulty;
 
     #[test]
     fn test_block_creation() {
-        let prev_hash =
-            "0000000000000000000000000000000000000000000000000000000000000000".to_string();
-        let transactions = vec!["tx1".to_string(), "tx2".to_string()];
+        let prev_hash = Hash256::ZERO;
+        let transactions = vec![
+            Transaction::memo("tx1").unwrap(),
+            Transaction::memo("tx2").unwrap(),
+        ];
         let difficulty = 16;
+        let bits = compact_from_difficulty(difficulty);
 
-        let block = Block::new(prev_hash, transactions.clone(), difficulty);
+        let block = Block::new(prev_hash, transactions.clone(), bits).unwrap();
 
         assert_eq!(block.transactions, transactions);
-        assert!(!block.hash.is_empty());
+        assert_eq!(block.header.nonce, 0);
+        assert_eq!(block.hash, block.calculate_hash());
     }
 
     #[test]
     fn test_calculate_hash() {
-        let prev_hash =
-            "0000000000000000000000000000000000000000000000000000000000000000".to_string();
-        let transactions = vec!["tx1".to_string()];
+        let prev_hash = Hash256::ZERO;
+        let transactions = vec![Transaction::memo("tx1").unwrap()];
         let difficulty = 16;
+        let bits = compact_from_difficulty(difficulty);
 
-        let block = Block::new(prev_hash, transactions, difficulty);
+        let block = Block::new(prev_hash, transactions, bits).unwrap();
         let hash = block.calculate_hash();
 
-        assert_eq!(hash.len(), 32); // Sha256 produces a 32-byte hash
+        assert_eq!(hash.as_bytes().len(), 32); // Sha256 produces a 32-byte hash
     }
 
     // New tests added
     #[test]
     fn test_mine_valid_hash() {
-        let prev_hash =
-            "0000000000000000000000000000000000000000000000000000000000000000".to_string();
-        let transactions = vec!["tx1".to_string()];
+        let prev_hash = Hash256::ZERO;
+        let transactions = vec![Transaction::memo("tx1").unwrap()];
         let difficulty = 16;
+        let bits = compact_from_difficulty(difficulty);
 
-        let mut block = Block::new(prev_hash, transactions, difficulty);
-        block.mine();
+        let block = Block::new(prev_hash, transactions, bits)
+            .unwrap()
+            .mine(&Miner::new(4))
+            .unwrap();
 
         assert!(
             block
                 .hash
+                .as_bytes()
                 .starts_with(&vec![0u8; (difficulty / 8) as usize])
-        ); 
+        );
+        assert_eq!(block.verify(), Ok(()));
+        assert_eq!(block.check_work(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_valid_block() {
+        let prev_hash = Hash256::ZERO;
+        let block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            compact_from_difficulty(8),
+        )
+        .unwrap()
+        .mine(&Miner::new(1))
+        .unwrap();
+
+        assert_eq!(block.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_tampered_block() {
+        let prev_hash = Hash256::ZERO;
+        let mut block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            compact_from_difficulty(8),
+        )
+        .unwrap();
+        block.transactions[0].memo = "tx2".to_string();
+
+        assert_eq!(block.verify(), Err(InvalidBlock::MerkleRootMismatch));
+    }
+
+    #[test]
+    fn test_verify_tampered_header() {
+        let prev_hash = Hash256::ZERO;
+        let mut block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            compact_from_difficulty(8),
+        )
+        .unwrap();
+        block.header.nonce += 1;
+
+        assert_eq!(block.verify(), Err(InvalidBlock::HashMismatch));
+    }
+
+    #[test]
+    fn test_verify_unsupported_version() {
+        let prev_hash = Hash256::ZERO;
+        let mut block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            compact_from_difficulty(8),
+        )
+        .unwrap();
+        block.header.version = 1;
+
+        assert_eq!(block.verify(), Err(InvalidBlock::UnsupportedVersion(1)));
+    }
+
+    #[test]
+    fn test_verify_invalid_signature() {
+        let prev_hash = Hash256::ZERO;
+        let unsigned = Transaction::new("alice", "bob", 5, 0).unwrap();
+        let block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap(), unsigned],
+            compact_from_difficulty(8),
+        )
+        .unwrap();
+
+        assert_eq!(
+            block.verify(),
+            Err(InvalidBlock::InvalidSignature { index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_merkle_proof_for_transaction() {
+        let prev_hash = Hash256::ZERO;
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|i| Transaction::memo(format!("tx{}", i)).unwrap())
+            .collect();
+        let block = Block::new(prev_hash, transactions, compact_from_difficulty(8)).unwrap();
+
+        let proof = block.merkle_proof(3).unwrap();
+        let root = block.header.merkle_root.as_bytes();
+        assert!(proof.verify(&block.transactions[3].id(), root));
+        assert!(!proof.verify(&block.transactions[2].id(), root));
+        assert!(block.merkle_proof(5).is_none());
+    }
+
+    #[test]
+    fn test_verify_invalid_target() {
+        let prev_hash = Hash256::ZERO;
+        let mut block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            0x207f_ffff,
+        )
+        .unwrap();
+        block.header.bits = 0x2080_0001;
+        block.hash = block.calculate_hash();
+
+        assert_eq!(block.verify(), Ok(()));
+        assert_eq!(block.check_work(), Err(InvalidBlock::InvalidTarget));
+        assert_eq!(block.work(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_work_follows_target() {
+        let prev_hash = Hash256::ZERO;
+        let block = Block::new(
+            prev_hash,
+            vec![Transaction::memo("tx1").unwrap()],
+            0x207f_ffff,
+        )
+        .unwrap();
+
+        assert_eq!(block.work(), U256::from_u64(2));
     }
 }
@@ -1,36 +1,77 @@
+use crate::error::Error;
+use crate::utils::hash::Hash256;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BlockHeader {
+    pub version: u32,
     pub timestamp: u64,
-    pub prev_hash: Vec<u8>,
+    pub prev_hash: Hash256,
+    pub merkle_root: Hash256,
     pub nonce: u64,
-    pub difficulty: u32,
+    pub bits: u32,
 }
 
 /// Represents the header of a block in the blockchain.
 ///
 /// This struct contains essential information for each block, including:
+/// - `version`: The header encoding version, currently `BlockHeader::VERSION`.
 /// - `timestamp`: The time at which the block was created, measured in seconds since the UNIX epoch.
-/// - `prev_hash`: A vector of bytes representing the hash of the previous block in the chain.
+/// - `prev_hash`: The hash of the previous block in the chain, zero for the genesis block.
+/// - `merkle_root`: The Merkle root of the block's transaction hashes.
 /// - `nonce`: A number used for mining, initialized to 0.
-/// - `difficulty`: The difficulty level for mining the block.
+/// - `bits`: The compact encoding of the 256-bit target the block hash may not exceed (see
+///   `target::target_from_compact`).
 ///
-/// The `new` function initializes a new `BlockHeader` with the provided previous hash and difficulty,
-/// setting the timestamp to the current time.
+/// The `new` function initializes a new `BlockHeader` with the provided previous hash, Merkle root
+/// and compact target, setting the timestamp to the current time. It fails with `Error::Clock`
+/// if the system clock is set before the UNIX epoch.
+///
+/// `encode` produces the canonical byte encoding that block hashes are computed over. All
+/// integers are little-endian and hash fields are 32 bytes:
+///
+/// | Offset | Size | Field         |
+/// | ------ | ---- | ------------- |
+/// | 0      | 4    | `version`     |
+/// | 4      | 8    | `timestamp`   |
+/// | 12     | 32   | `prev_hash`   |
+/// | 44     | 32   | `merkle_root` |
+/// | 76     | 4    | `bits`        |
+/// | 80     | 8    | `nonce`       |
+///
+/// `hash` returns the SHA-256 of that encoding. Version 1 headers stored a count of leading zero
+/// bits at offset 76 and are no longer accepted.
 impl BlockHeader {
-    pub fn new(prev_hash: Vec<u8>, difficulty: u32) -> Self {
-        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-        Self {
+    pub const VERSION: u32 = 2;
+    pub const ENCODED_LEN: usize = 88;
+
+    pub fn new(prev_hash: Hash256, merkle_root: Hash256, bits: u32) -> Result<Self, Error> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Self {
+            version: Self::VERSION,
             timestamp,
             prev_hash,
+            merkle_root,
             nonce: 0,
-            difficulty,
-        }
+            bits,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[0..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[12..44].copy_from_slice(self.prev_hash.as_bytes());
+        bytes[44..76].copy_from_slice(self.merkle_root.as_bytes());
+        bytes[76..80].copy_from_slice(&self.bits.to_le_bytes());
+        bytes[80..88].copy_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    pub fn hash(&self) -> Hash256 {
+        Hash256(Sha256::digest(self.encode()).into())
     }
 }
 
@@ -40,41 +81,107 @@ mod tests {
 
     #[test]
     fn test_block_header_creation() {
-        let prev_hash = vec![0u8; 32];
-        let difficulty = 2;
-        let block_header = BlockHeader::new(prev_hash.clone(), difficulty);
+        let prev_hash = Hash256([0u8; 32]);
+        let bits = 0x1f00_ffff;
+        let block_header = BlockHeader::new(prev_hash, Hash256::ZERO, bits).unwrap();
 
         assert_eq!(block_header.prev_hash, prev_hash);
-        assert_eq!(block_header.difficulty, difficulty);
+        assert_eq!(block_header.bits, bits);
         assert!(block_header.timestamp > 0);
         assert_eq!(block_header.nonce, 0);
     }
 
     #[test]
-    fn test_block_header_difficulty() {
-        let prev_hash = vec![1u8; 32];
-        let difficulty = 5;
-        let block_header = BlockHeader::new(prev_hash.clone(), difficulty);
+    fn test_block_header_bits() {
+        let prev_hash = Hash256([1u8; 32]);
+        let bits = 0x2000_ffff;
+        let block_header = BlockHeader::new(prev_hash, Hash256::ZERO, bits).unwrap();
 
-        assert_eq!(block_header.difficulty, difficulty);
+        assert_eq!(block_header.bits, bits);
     }
 
     // New tests added below
     #[test]
     fn test_block_header_nonce_initialization() {
-        let prev_hash = vec![2u8; 32];
-        let difficulty = 3;
-        let block_header = BlockHeader::new(prev_hash.clone(), difficulty);
+        let prev_hash = Hash256([2u8; 32]);
+        let bits = 0x207f_ffff;
+        let block_header = BlockHeader::new(prev_hash, Hash256::ZERO, bits).unwrap();
 
         assert_eq!(block_header.nonce, 0);
     }
 
     #[test]
     fn test_block_header_timestamp() {
-        let prev_hash = vec![3u8; 32];
-        let difficulty = 4;
-        let block_header = BlockHeader::new(prev_hash.clone(), difficulty);
+        let prev_hash = Hash256([3u8; 32]);
+        let bits = 0x1d00_ffff;
+        let block_header = BlockHeader::new(prev_hash, Hash256::ZERO, bits).unwrap();
 
         assert!(block_header.timestamp > 0);
     }
+
+    #[test]
+    fn test_block_header_merkle_root() {
+        let merkle_root = Hash256([4u8; 32]);
+        let block_header = BlockHeader::new(Hash256::ZERO, merkle_root, 0x1d00_ffff).unwrap();
+
+        assert_eq!(block_header.merkle_root, merkle_root);
+    }
+
+    fn test_vector_header() -> BlockHeader {
+        BlockHeader {
+            version: BlockHeader::VERSION,
+            timestamp: 1_700_000_000,
+            prev_hash: Hash256([0x11; 32]),
+            merkle_root: Hash256([0x22; 32]),
+            nonce: 42,
+            bits: 0x1d00_ffff,
+        }
+    }
+
+    #[test]
+    fn test_block_header_encoding_vector() {
+        let expected = "0200000000f15365000000001111111111111111111111111111111111111111111111111111111111111111\
+                        2222222222222222222222222222222222222222222222222222222222222222ffff001d2a00000000000000";
+        let encoded = test_vector_header().encode();
+
+        assert_eq!(encoded.len(), BlockHeader::ENCODED_LEN);
+        assert_eq!(crate::utils::hash::bytes_to_hex_string(&encoded), expected);
+    }
+
+    #[test]
+    fn test_block_header_hash_vectors() {
+        assert_eq!(
+            test_vector_header().hash().to_string(),
+            "f26a68adb797ada8fe6050f0495bcb9376c5e035ddad83461310abb13f5f2107"
+        );
+
+        let zero = BlockHeader {
+            version: BlockHeader::VERSION,
+            timestamp: 0,
+            prev_hash: Hash256::ZERO,
+            merkle_root: Hash256::ZERO,
+            nonce: 0,
+            bits: 0x207f_ffff,
+        };
+        assert_eq!(
+            zero.hash().to_string(),
+            "bbee311a4eec7e1144b8509010cf45d52d4f6c2daeb56b10d7b732bf1208a19e"
+        );
+    }
+
+    #[test]
+    fn test_block_header_hash_covers_every_field() {
+        let base = test_vector_header();
+        let mut variants = vec![base.clone(); 6];
+        variants[0].version += 1;
+        variants[1].timestamp += 1;
+        variants[2].prev_hash.0[31] ^= 1;
+        variants[3].merkle_root.0[0] ^= 1;
+        variants[4].bits += 1;
+        variants[5].nonce += 1;
+
+        for variant in variants {
+            assert_ne!(variant.hash(), base.hash());
+        }
+    }
 }
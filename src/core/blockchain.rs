@@ -1,81 +1,681 @@
 use super::block::Block;
-use crate::utils::hash::bytes_to_hex_string;
+use super::consensus::{Consensus, ConsensusEngine, Sealer};
+use super::events::ChainEvent;
+use super::miner::{CancelToken, Miner, MiningOutcome, MiningStats};
+use super::reward::RewardSchedule;
+use super::state::WorldState;
+use super::target::{U256, compact_from_difficulty};
+use super::transaction::Transaction;
+use super::utxo::{LedgerMode, OutPoint, TxOutput, UtxoSet};
+use super::validation::{InvalidBlock, ValidationError};
+use crate::error::Error;
+use crate::utils::crypto::Keypair;
+use crate::utils::hash::Hash256;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How far past the local clock, in seconds, a block's timestamp may be. Without a bound a
+/// miner could claim future timestamps to lower the difficulty set by a retarget rule.
+pub const MAX_FUTURE_DRIFT: u64 = 2 * 60 * 60;
+
+/// Where a block passed to `Blockchain::submit_block` ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// The block was already known, on the active chain or a side branch.
+    Known,
+    /// The block is valid but its branch has no more work than the active chain.
+    SideChain,
+    /// The block is the new tip of the active chain, possibly after a reorganization.
+    Active,
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Blockchain {
+pub struct Blockchain<C = Consensus> {
     pub chain: Vec<Block>,
+    side_blocks: BTreeMap<Hash256, Block>,
     pub difficulty: u32,
+    pub consensus: C,
+    pub ledger: LedgerMode,
+    pub reward: RewardSchedule,
+    #[serde(skip)]
+    state: WorldState,
+    #[serde(skip)]
+    utxos: UtxoSet,
+    #[serde(skip)]
+    pub miner: Miner,
+    #[serde(skip)]
+    pub signer: Option<Arc<Keypair>>,
+    #[serde(skip)]
+    subscribers: Vec<Sender<ChainEvent>>,
 }
 
 /// A structure representing a blockchain.
 ///
 /// The `Blockchain` struct manages a chain of blocks, allowing for the creation of new blocks
-/// and retrieval of the last block in the chain.
+/// and retrieval of the last block in the chain. Depending on `ledger` it also keeps either the
+/// `WorldState` or the `UtxoSet` derived from its blocks. The ledger state is not part of the
+/// serialized chain; it is persisted separately and reattached with `restore_state` or
+/// `restore_utxo_set`, or replayed with `rebuild_state`. Neither are `miner` and `signer`, the
+/// local resources used to seal blocks: the number of mining threads, one per CPU by default,
+/// and the key that signs blocks on a proof-of-authority or proof-of-stake chain.
+///
+/// `consensus` decides who may seal each block, how the seal is checked and how much each
+/// block weighs in fork choice. It is any `ConsensusEngine`, by default one of the built-in
+/// engines configured by `Consensus`. `difficulty` is the genesis difficulty in leading zero bits;
+/// the genesis header carries the equivalent compact target and each later block must carry
+/// the `bits` the engine derives from the blocks before it.
+///
+/// Every block after genesis starts with a coinbase transaction paying the miner at most the
+/// reward given by `reward` for its height plus the fees of the block's other transactions.
+///
+/// Besides the active `chain`, the blockchain keeps every valid block that extends some known
+/// block in a tree of side branches. The active chain always ends at the tip with the most
+/// cumulative work; ties keep the branch that was seen first. Switching branches disconnects the
+/// abandoned blocks, which stay in the tree, and replays the ledger state along the new branch.
+/// Changes to the active chain are sent to every receiver returned by `subscribe`.
 ///
 /// # Methods
 ///
-/// - `new(difficulty: u32) -> Self`: Creates a new instance of `Blockchain` with the specified
-///   difficulty level. It initializes the chain and creates the genesis block.
+/// - `new(difficulty: u32) -> Result<Self, Error>`: Creates a new instance of `Blockchain`
+///   with the specified difficulty level. It initializes the chain and creates the genesis
+///   block.
 ///
-/// - `create_genesis_block(&mut self)`: Private method that creates the first block in the
-///   blockchain, known as the genesis block, and adds it to the chain.
+/// - `with_allocations(difficulty: u32, allocations: &[(String, u64)]) -> Result<Self, Error>`:
+///   Creates a new account blockchain whose genesis block credits each address with the given
+///   balance. Fails if the allocations overflow a balance.
+///
+/// - `with_ledger(difficulty: u32, ledger: LedgerMode, allocations: &[(String, u64)])
+///   -> Result<Self, Error>`: Like `with_allocations`, for either ledger mode. On a UTXO chain
+///   the genesis block holds one output per allocation.
+///
+/// - `with_consensus(difficulty: u32, ledger: LedgerMode, consensus: C, allocations:
+///   &[(String, u64)]) -> Result<Self, Error>`: Like `with_ledger`, sealed by the given
+///   consensus engine instead of proof of work.
+///
+/// - `create_genesis_block(&mut self, transactions: Vec<Transaction>) -> Result<(), Error>`:
+///   Private method that creates the first block in the blockchain, known as the genesis
+///   block, and adds it to the chain.
 ///
 /// - `get_last_block(&self) -> Option<&Block>`: Returns a reference to the last block in the
 ///   blockchain, or `None` if the chain is empty.
 ///
-/// - `add_block(&mut self, transactions: Vec<String>) -> Result<(), &'static str>`: Adds a new
-///   block containing the provided transactions to the blockchain. Returns an error if the
-///   blockchain is empty.
+/// - `prepare_block(&self, miner: &str, transactions: Vec<Transaction>) -> Result<Block, Error>`:
+///   Builds the unsealed next block, containing a coinbase paying the reward and fees to
+///   `miner` followed by the provided transactions. Returns `Error::InvalidBlock` if the
+///   blockchain is empty, a transaction has an invalid signature, the miner or a transfer recipient is a
+///   malformed address, or the transactions overdraw an account, reuse a nonce, spend an
+///   unknown or already spent output or create coins themselves. The block can be sealed with
+///   `seal` and added with `submit_block`.
+///
+/// - `seal(&self, block: &Block, cancel: &CancelToken, progress: impl FnMut(&MiningStats))
+///   -> Result<MiningOutcome, Error>`: Seals a block prepared on the current tip with the
+///   consensus engine, `miner` and `signer`, failing with `Error::Consensus` if this node may
+///   not seal it.
+///
+/// - `add_block(&mut self, miner: &str, transactions: Vec<Transaction>) -> Result<(), Error>`:
+///   Prepares, seals and appends the next block, failing for the same reasons as
+///   `prepare_block` and `seal`.
+///
+/// - `submit_block(&mut self, block: Block) -> Result<BlockStatus, Error>`: Adds a block
+///   produced elsewhere that extends any known block, reorganizing the active chain if the
+///   block's branch now has the most work. A block that fails validation, or a branch whose
+///   ledger state cannot be replayed, is rejected and leaves the active chain unchanged.
+///
+/// - `side_blocks(&self) -> impl Iterator<Item = &Block>`: Returns the known blocks that are not
+///   on the active chain.
+///
+/// - `subscribe(&mut self) -> Receiver<ChainEvent>`: Returns a receiver for every later change
+///   to the active chain.
+///
+/// - `next_bits(&self) -> u32`: Returns the compact target required of the next block.
+///
+/// - `cumulative_work(&self) -> Vec<U256>` and `chain_work(&self) -> U256`: Return the total
+///   work of the chain, as weighed by the consensus engine, up to and including each block,
+///   and up to the tip.
+///
+/// - `check_transactions(&self, transactions: &[Transaction]) -> Result<(), InvalidBlock>`:
+///   Checks `transactions` as the contents of the next block, reporting the first one that
+///   cannot be added; `pending_ledger` returns the state to add them to one at a time.
+///
+/// - `balance_of(&self, address: &str) -> u64` and `nonce_of(&self, address: &str) -> u64`:
+///   Return the balance and next nonce of an account at the tip of the chain. On a UTXO chain
+///   the balance is the sum of the address's unspent outputs and the nonce is always zero.
+///
+/// - `validate(&self) -> Result<(), Error>`: Walks the chain from genesis and returns
+///   `Error::Validation` with the height and reason of the first block that fails
+///   verification.
 impl Blockchain {
-    pub fn new(difficulty: u32) -> Self {
-        let mut blockchain = Self {
+    pub fn new(difficulty: u32) -> Result<Self, Error> {
+        Self::with_ledger(difficulty, LedgerMode::Account, &[])
+    }
+
+    pub fn with_allocations(difficulty: u32, allocations: &[(String, u64)]) -> Result<Self, Error> {
+        Self::with_ledger(difficulty, LedgerMode::Account, allocations)
+    }
+
+    pub fn with_ledger(
+        difficulty: u32,
+        ledger: LedgerMode,
+        allocations: &[(String, u64)],
+    ) -> Result<Self, Error> {
+        Self::with_consensus(difficulty, ledger, Consensus::default(), allocations)
+    }
+}
+
+impl<C: ConsensusEngine> Blockchain<C> {
+    pub fn with_consensus(
+        difficulty: u32,
+        ledger: LedgerMode,
+        consensus: C,
+        allocations: &[(String, u64)],
+    ) -> Result<Self, Error> {
+        let mut blockchain = Self::empty(difficulty, ledger, consensus);
+        let mut transactions = vec![Transaction::memo("genesis")?];
+        match ledger {
+            LedgerMode::Account => {
+                for (address, amount) in allocations {
+                    transactions.push(Transaction::new("", address, *amount, 0)?);
+                }
+            }
+            LedgerMode::Utxo if !allocations.is_empty() => {
+                let outputs = allocations
+                    .iter()
+                    .map(|(address, amount)| TxOutput {
+                        value: *amount,
+                        locking_key: address.clone(),
+                    })
+                    .collect();
+                transactions.push(Transaction::utxo(Vec::new(), outputs)?);
+            }
+            LedgerMode::Utxo => {}
+        }
+        blockchain.create_genesis_block(transactions)?;
+        Ok(blockchain)
+    }
+
+    fn empty(difficulty: u32, ledger: LedgerMode, consensus: C) -> Self {
+        Self {
             chain: Vec::new(),
+            side_blocks: BTreeMap::new(),
             difficulty,
-        };
-        blockchain.create_genesis_block();
-        blockchain
+            consensus,
+            ledger,
+            reward: RewardSchedule::default(),
+            state: WorldState::new(),
+            utxos: UtxoSet::new(),
+            miner: Miner::default(),
+            signer: None,
+            subscribers: Vec::new(),
+        }
     }
 
-    fn create_genesis_block(&mut self) {
-        let genesis_block =
-            Block::new("0".repeat(64), vec!["genesis".to_string()], self.difficulty);
+    fn create_genesis_block(&mut self, transactions: Vec<Transaction>) -> Result<(), Error> {
+        let unsealed = Block::new(Hash256::ZERO, transactions, self.genesis_bits())?;
+        let MiningOutcome::Mined {
+            block: genesis_block,
+            ..
+        } = self.seal(&unsealed, &CancelToken::new(), |_| {})?
+        else {
+            unreachable!("mining is never cancelled");
+        };
 
-        self.chain.push(genesis_block.clone());
-        println!("Genesis block initialized.");
-        println!("Hash: {}", bytes_to_hex_string(&genesis_block.hash));
-        println!("Transactions: {:?}", genesis_block.transactions);
-        println!("Nonce: {}", genesis_block.header.nonce);
+        self.apply_ledger(&genesis_block)?;
+        self.chain.push(genesis_block);
+        Ok(())
     }
 
     pub fn get_last_block(&self) -> Option<&Block> {
         self.chain.last()
     }
 
-    pub fn add_block(&mut self, transactions: Vec<String>) -> Result<(), &'static str> {
-        let last_block = self
-            .get_last_block()
-            .ok_or("Blockchain is empty. Cannot add block.")?;
+    pub fn add_block(&mut self, miner: &str, transactions: Vec<Transaction>) -> Result<(), Error> {
+        let unsealed = self.prepare_block(miner, transactions)?;
+        let MiningOutcome::Mined { block, .. } =
+            self.seal(&unsealed, &CancelToken::new(), |_| {})?
+        else {
+            unreachable!("mining is never cancelled");
+        };
+        self.submit_block(block)?;
+        Ok(())
+    }
 
-        let new_block = Block::new(
-            bytes_to_hex_string(last_block.hash.as_slice()),
-            transactions,
-            self.difficulty,
-        );
+    pub fn prepare_block(
+        &self,
+        miner: &str,
+        transactions: Vec<Transaction>,
+    ) -> Result<Block, Error> {
+        let last_block = self.get_last_block().ok_or(InvalidBlock::MissingGenesis)?;
+        let height = self.chain.len();
+        let value = self
+            .reward
+            .reward_at(height)
+            .saturating_add(total_fees(&transactions));
+        let mut transactions = transactions;
+        transactions.insert(0, Transaction::coinbase(height, miner, value, self.ledger)?);
+        self.check_transactions(&transactions)?;
+
+        Block::new(last_block.hash, transactions, self.next_bits())
+    }
+
+    pub fn seal(
+        &self,
+        block: &Block,
+        cancel: &CancelToken,
+        mut progress: impl FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        let sealer = Sealer {
+            miner: &self.miner,
+            signer: self.signer.as_deref(),
+            cancel,
+        };
+        self.consensus
+            .seal(&self.chain, block, &sealer, &mut progress)
+    }
+
+    pub fn submit_block(&mut self, block: Block) -> Result<BlockStatus, Error> {
+        if self.contains_block(&block.hash) {
+            return Ok(BlockStatus::Known);
+        }
+        let (fork_height, ancestors) = self.branch_to(&block.header.prev_hash)?;
+        if ancestors.is_empty() && fork_height + 1 == self.chain.len() {
+            self.validate_block(&self.chain, &block)?;
+            self.apply_ledger(&block)?;
+            self.chain.push(block.clone());
+            self.emit(ChainEvent::BlockConnected {
+                height: fork_height + 1,
+                block,
+            });
+            return Ok(BlockStatus::Active);
+        }
+
+        // Side blocks are only checked against their branch here, as the ones read from a
+        // store have not been validated at all.
+        let mut candidate = self.chain[..=fork_height].to_vec();
+        for ancestor in ancestors {
+            self.validate_block(&candidate, &ancestor)?;
+            candidate.push(ancestor);
+        }
+        self.validate_block(&candidate, &block)?;
+        if self.total_work(&candidate) + self.consensus.block_work(&block) <= self.chain_work() {
+            self.side_blocks.insert(block.hash, block);
+            return Ok(BlockStatus::SideChain);
+        }
+        candidate.push(block);
+        self.reorganize(fork_height, candidate)?;
+        Ok(BlockStatus::Active)
+    }
+
+    /// Returns the height of the active block that `hash` descends from, and the side blocks
+    /// from just above it up to and including `hash`.
+    fn branch_to(&self, hash: &Hash256) -> Result<(usize, Vec<Block>), InvalidBlock> {
+        let mut ancestors = Vec::new();
+        let mut parent = hash;
+        loop {
+            if let Some(height) = self.chain.iter().rposition(|block| block.hash == *parent) {
+                ancestors.reverse();
+                return Ok((height, ancestors));
+            }
+            let block = self
+                .side_blocks
+                .get(parent)
+                .ok_or(InvalidBlock::UnknownParent)?;
+            parent = &block.header.prev_hash;
+            ancestors.push(block.clone());
+        }
+    }
+
+    fn reorganize(
+        &mut self,
+        fork_height: usize,
+        candidate: Vec<Block>,
+    ) -> Result<(), InvalidBlock> {
+        let mut previous = std::mem::replace(&mut self.chain, candidate);
+        if let Err(err) = self.replay_ledger() {
+            self.chain = previous;
+            return Err(err.reason);
+        }
+        let disconnected = previous.split_off(fork_height + 1);
+        let connected = self.chain[fork_height + 1..].to_vec();
+        for (offset, block) in disconnected.iter().enumerate().rev() {
+            self.emit(ChainEvent::BlockDisconnected {
+                height: fork_height + 1 + offset,
+                block: block.clone(),
+            });
+        }
+        for (offset, block) in connected.iter().enumerate() {
+            self.side_blocks.remove(&block.hash);
+            self.emit(ChainEvent::BlockConnected {
+                height: fork_height + 1 + offset,
+                block: block.clone(),
+            });
+        }
+        self.emit(ChainEvent::Reorganized {
+            fork_height,
+            disconnected: disconnected.len(),
+            connected: connected.len(),
+        });
+        for block in disconnected {
+            self.side_blocks.insert(block.hash, block);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds a blockchain from stored blocks without validating them. The ledger state is
+    /// empty until it is restored or rebuilt.
+    pub fn from_parts(
+        difficulty: u32,
+        ledger: LedgerMode,
+        consensus: C,
+        chain: Vec<Block>,
+        side_blocks: impl IntoIterator<Item = Block>,
+    ) -> Self {
+        let mut blockchain = Self::empty(difficulty, ledger, consensus);
+        blockchain.chain = chain;
+        blockchain.side_blocks = side_blocks
+            .into_iter()
+            .map(|block| (block.hash, block))
+            .collect();
+        blockchain
+    }
+
+    pub fn side_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.side_blocks.values()
+    }
+
+    pub fn contains_block(&self, hash: &Hash256) -> bool {
+        self.side_blocks.contains_key(hash) || self.chain.iter().any(|block| block.hash == *hash)
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: ChainEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    pub fn next_bits(&self) -> u32 {
+        self.expected_bits(&self.chain)
+    }
+
+    fn genesis_bits(&self) -> u32 {
+        compact_from_difficulty(self.difficulty)
+    }
+
+    fn expected_bits(&self, previous: &[Block]) -> u32 {
+        self.consensus.next_bits(previous, self.genesis_bits())
+    }
+
+    pub fn cumulative_work(&self) -> Vec<U256> {
+        self.chain
+            .iter()
+            .scan(U256::ZERO, |total, block| {
+                *total = *total + self.consensus.block_work(block);
+                Some(*total)
+            })
+            .collect()
+    }
+
+    pub fn chain_work(&self) -> U256 {
+        self.total_work(&self.chain)
+    }
+
+    fn total_work(&self, blocks: &[Block]) -> U256 {
+        blocks.iter().fold(U256::ZERO, |total, block| {
+            total + self.consensus.block_work(block)
+        })
+    }
+
+    /// Checks the signatures, recipient addresses and ledger rules of `transactions` as the
+    /// contents of the next block, without mining it.
+    pub fn check_transactions(&self, transactions: &[Transaction]) -> Result<(), InvalidBlock> {
+        let mut pending = self.pending_ledger();
+        transactions.iter().try_for_each(|tx| pending.push(tx))
+    }
+
+    /// Returns a copy of the ledger state at the tip, to which the transactions of the next
+    /// block can be added one at a time.
+    pub fn pending_ledger(&self) -> PendingLedger {
+        let ledger = match self.ledger {
+            LedgerMode::Account => Ledger::Account(self.state.clone()),
+            LedgerMode::Utxo => Ledger::Utxo(self.utxos.clone(), BTreeSet::new()),
+        };
+        PendingLedger {
+            tip: self.get_last_block().map(|block| block.hash),
+            ledger,
+            len: 0,
+        }
+    }
+
+    fn apply_ledger(&mut self, block: &Block) -> Result<(), InvalidBlock> {
+        match self.ledger {
+            LedgerMode::Account => self.state.apply_block(block).map_err(InvalidBlock::Ledger),
+            LedgerMode::Utxo => self.utxos.apply_block(block).map_err(InvalidBlock::Utxo),
+        }
+    }
+
+    pub fn balance_of(&self, address: &str) -> u64 {
+        match self.ledger {
+            LedgerMode::Account => self.state.balance_of(address),
+            LedgerMode::Utxo => self.utxos.balance_of(address),
+        }
+    }
+
+    pub fn nonce_of(&self, address: &str) -> u64 {
+        self.state.nonce_of(address)
+    }
+
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    pub fn utxo_set(&self) -> &UtxoSet {
+        &self.utxos
+    }
+
+    /// Attaches a previously persisted state if it was built from exactly this chain.
+    ///
+    /// Returns `false` and leaves the current state untouched otherwise.
+    pub fn restore_state(&mut self, state: WorldState) -> bool {
+        let tip = self.chain.last().map(|block| block.hash);
+        if state.height() != self.chain.len() || Some(state.tip()) != tip {
+            return false;
+        }
+        self.state = state;
+        true
+    }
+
+    /// Attaches a previously persisted UTXO set if it was built from exactly this chain.
+    ///
+    /// Returns `false` and leaves the current set untouched otherwise.
+    pub fn restore_utxo_set(&mut self, utxos: UtxoSet) -> bool {
+        let tip = self.chain.last().map(|block| block.hash);
+        if utxos.height() != self.chain.len() || Some(utxos.tip()) != tip {
+            return false;
+        }
+        self.utxos = utxos;
+        true
+    }
+
+    /// Replays every block from genesis to rebuild the ledger state of the chain's mode.
+    pub fn rebuild_state(&mut self) -> Result<(), Error> {
+        Ok(self.replay_ledger()?)
+    }
+
+    fn replay_ledger(&mut self) -> Result<(), ValidationError> {
+        match self.ledger {
+            LedgerMode::Account => {
+                self.state = WorldState::from_blocks(&self.chain).map_err(|(height, err)| {
+                    ValidationError {
+                        height,
+                        reason: InvalidBlock::Ledger(err),
+                    }
+                })?;
+            }
+            LedgerMode::Utxo => {
+                self.utxos =
+                    UtxoSet::from_blocks(&self.chain).map_err(|(height, err)| ValidationError {
+                        height,
+                        reason: InvalidBlock::Utxo(err),
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.chain.is_empty() {
+            return Err(ValidationError {
+                height: 0,
+                reason: InvalidBlock::MissingGenesis,
+            }
+            .into());
+        }
+        for (height, block) in self.chain.iter().enumerate() {
+            self.validate_block(&self.chain[..height], block)
+                .map_err(|reason| ValidationError { height, reason })?;
+        }
+        Ok(())
+    }
+
+    /// Checks `block` as the successor of `previous`, without applying its transactions.
+    fn validate_block(&self, previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+        let height = previous.len();
+        let expected = self.expected_bits(previous);
+        if block.header.bits != expected {
+            return Err(InvalidBlock::DifficultyMismatch {
+                expected,
+                found: block.header.bits,
+            });
+        }
+        block.verify()?;
+        // A clock set before the epoch cannot bound anything, so the check is skipped.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(u64::MAX, |elapsed| elapsed.as_secs());
+        if block.header.timestamp > now.saturating_add(MAX_FUTURE_DRIFT) {
+            return Err(InvalidBlock::TimestampTooFarAhead);
+        }
+        self.consensus.verify_seal(previous, block)?;
+        match previous.last() {
+            Some(prev) => {
+                if block.header.prev_hash != prev.hash {
+                    return Err(InvalidBlock::BrokenLink);
+                }
+                if block.header.timestamp < prev.header.timestamp {
+                    return Err(InvalidBlock::TimestampNotMonotonic);
+                }
+                self.validate_coinbase(height, block)?;
+            }
+            None => {
+                if !block.header.prev_hash.is_zero() {
+                    return Err(InvalidBlock::BrokenLink);
+                }
+            }
+        }
+        Ok(())
+    }
 
-        self.chain.push(new_block);
+    fn validate_coinbase(&self, height: usize, block: &Block) -> Result<(), InvalidBlock> {
+        let (coinbase, rest) = block
+            .transactions
+            .split_first()
+            .filter(|(coinbase, _)| coinbase.is_coinbase())
+            .ok_or(InvalidBlock::MissingCoinbase)?;
+        let allowed = self
+            .reward
+            .reward_at(height)
+            .saturating_add(total_fees(rest));
+        let found = coinbase.output_value();
+        if found > allowed {
+            return Err(InvalidBlock::CoinbaseTooLarge { allowed, found });
+        }
         Ok(())
     }
 }
 
-pub struct BlockchainIterator<'a> {
-    blockchain: &'a Blockchain,
+#[derive(Debug, Clone)]
+enum Ledger {
+    Account(WorldState),
+    Utxo(UtxoSet, BTreeSet<OutPoint>),
+}
+
+/// The ledger state of a chain with the transactions of its next block applied on top, as
+/// returned by `Blockchain::pending_ledger`.
+///
+/// Each transaction is checked against the state left by the ones before it, so adding one
+/// costs the same however many are already pending.
+///
+/// # Methods
+///
+/// - `push(&mut self, tx: &Transaction) -> Result<(), InvalidBlock>`: Checks the signature,
+///   recipient addresses and ledger rules of `tx` as the next transaction of the block and
+///   applies it, leaving the state unchanged if it is refused.
+///
+/// - `tip(&self) -> Option<Hash256>`: Returns the hash of the block the state was taken at.
+///
+/// - `len(&self) -> usize`: Returns the number of transactions pushed.
+#[derive(Debug, Clone)]
+pub struct PendingLedger {
+    tip: Option<Hash256>,
+    ledger: Ledger,
+    len: usize,
+}
+
+impl PendingLedger {
+    pub fn push(&mut self, tx: &Transaction) -> Result<(), InvalidBlock> {
+        let index = self.len;
+        if !tx.has_valid_signature() {
+            return Err(InvalidBlock::InvalidSignature { index });
+        }
+        if !tx.has_valid_recipients() {
+            return Err(InvalidBlock::InvalidRecipient { index });
+        }
+        match &mut self.ledger {
+            Ledger::Account(state) => state
+                .apply_transaction(index, tx)
+                .map_err(InvalidBlock::Ledger)?,
+            Ledger::Utxo(utxos, spent) => utxos
+                .apply_transaction(index, tx, spent)
+                .map_err(InvalidBlock::Utxo)?,
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn tip(&self) -> Option<Hash256> {
+        self.tip
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn total_fees(transactions: &[Transaction]) -> u64 {
+    transactions
+        .iter()
+        .fold(0, |sum, tx| sum.saturating_add(tx.fee))
+}
+
+pub struct BlockchainIterator<'a, C = Consensus> {
+    blockchain: &'a Blockchain<C>,
     current_index: usize,
 }
 
-impl<'a> IntoIterator for &'a Blockchain {
+impl<'a, C> IntoIterator for &'a Blockchain<C> {
     type Item = &'a Block;
-    type IntoIter = BlockchainIterator<'a>;
+    type IntoIter = BlockchainIterator<'a, C>;
 
     fn into_iter(self) -> Self::IntoIter {
         BlockchainIterator {
@@ -85,7 +685,7 @@ impl<'a> IntoIterator for &'a Blockchain {
     }
 }
 
-impl<'a> Iterator for BlockchainIterator<'a> {
+impl<'a, C> Iterator for BlockchainIterator<'a, C> {
     type Item = &'a Block;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -99,8 +699,8 @@ impl<'a> Iterator for BlockchainIterator<'a> {
     }
 }
 
-impl Blockchain {
-    pub fn iter(&self) -> BlockchainIterator {
+impl<C> Blockchain<C> {
+    pub fn iter(&self) -> BlockchainIterator<'_, C> {
         self.into_iter()
     }
 
@@ -112,111 +712,773 @@ impl Blockchain {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::consensus::poa::ProofOfAuthority;
+    use crate::core::consensus::pos::{ProofOfStake, Validator};
+    use crate::core::consensus::pow::ProofOfWork;
+    use crate::core::consensus::{SealError, seal_signer};
+    use crate::core::difficulty::{Retarget, RetargetRule};
+    use crate::core::target::{difficulty_from_target, target_from_compact};
+    use crate::utils::crypto::Keypair;
+    use std::time::Duration;
+
+    const MINER: &str = "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450";
+
+    /// Returns the error of a `validate` or `rebuild_state` that is expected to fail.
+    fn invalid_at(result: Result<(), Error>) -> ValidationError {
+        match result {
+            Err(Error::Validation(err)) => err,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    /// Returns the reason a block is expected to be rejected for.
+    fn rejected<T: std::fmt::Debug>(result: Result<T, Error>) -> InvalidBlock {
+        match result {
+            Err(Error::InvalidBlock(reason)) => reason,
+            other => panic!("expected an invalid block, got {:?}", other),
+        }
+    }
+
+    /// Mines a block at `height` on top of `parent` whose coinbase pays the full reward to
+    /// `miner`.
+    fn block_on(
+        blockchain: &Blockchain,
+        parent: &Block,
+        height: usize,
+        miner: &str,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let value = blockchain.reward.reward_at(height);
+        let mut transactions = transactions;
+        transactions.insert(
+            0,
+            Transaction::coinbase(height, miner, value, LedgerMode::Account).unwrap(),
+        );
+        Block::new(
+            parent.hash,
+            transactions,
+            compact_from_difficulty(blockchain.difficulty),
+        )
+        .unwrap()
+        .mine(&blockchain.miner)
+        .unwrap()
+    }
+
+    fn memos(block: &Block) -> Vec<&str> {
+        block
+            .transactions
+            .iter()
+            .filter(|tx| tx.is_memo_only())
+            .map(|tx| tx.memo.as_str())
+            .collect()
+    }
 
     #[test]
     fn test_create_genesis_block() {
-        let blockchain = Blockchain::new(2);
+        let blockchain = Blockchain::new(2).unwrap();
         assert_eq!(blockchain.chain.len(), 1);
-        assert_eq!(
-            blockchain.chain[0].transactions,
-            vec!["genesis".to_string()]
-        );
+        assert_eq!(memos(&blockchain.chain[0]), vec!["genesis"]);
     }
 
     #[test]
     fn test_add_block() {
-        let mut blockchain = Blockchain::new(2);
-        let result = blockchain.add_block(vec!["transaction1".to_string()]);
+        let mut blockchain = Blockchain::new(2).unwrap();
+        let result = blockchain.add_block(MINER, vec![Transaction::memo("transaction1").unwrap()]);
         assert!(result.is_ok());
         assert_eq!(blockchain.chain.len(), 2);
-        assert_eq!(
-            blockchain.chain[1].transactions,
-            vec!["transaction1".to_string()]
-        );
+        assert_eq!(memos(&blockchain.chain[1]), vec!["transaction1"]);
     }
 
     #[test]
     fn test_get_last_block() {
-        let mut blockchain = Blockchain::new(2);
+        let mut blockchain = Blockchain::new(2).unwrap();
         blockchain
-            .add_block(vec!["transaction1".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
             .unwrap();
         let last_block = blockchain.get_last_block().unwrap();
-        assert_eq!(last_block.transactions, vec!["transaction1".to_string()]);
+        assert_eq!(memos(last_block), vec!["transaction1"]);
     }
 
     // New tests added
     #[test]
     fn test_empty_blockchain() {
-        let blockchain = Blockchain::new(2);
+        let blockchain = Blockchain::new(2).unwrap();
         assert!(blockchain.get_last_block().is_some());
     }
 
     #[test]
     fn test_add_multiple_blocks() {
-        let mut blockchain = Blockchain::new(2);
+        let mut blockchain = Blockchain::new(2).unwrap();
         blockchain
-            .add_block(vec!["transaction1".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
             .unwrap();
         blockchain
-            .add_block(vec!["transaction2".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction2").unwrap()])
             .unwrap();
         assert_eq!(blockchain.chain.len(), 3);
-        assert_eq!(
-            blockchain.chain[2].transactions,
-            vec!["transaction2".to_string()]
-        );
+        assert_eq!(memos(&blockchain.chain[2]), vec!["transaction2"]);
     }
 
     #[test]
     fn test_iterate_blocks() {
-        let mut blockchain = Blockchain::new(2);
+        let mut blockchain = Blockchain::new(2).unwrap();
         blockchain
-            .add_block(vec!["transaction1".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
             .unwrap();
         blockchain
-            .add_block(vec!["transaction2".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction2").unwrap()])
             .unwrap();
 
         let mut iter = blockchain.iter();
-        assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["genesis".to_string()]
-        );
-        assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["transaction1".to_string()]
-        );
-        assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["transaction2".to_string()]
-        );
+        assert_eq!(memos(iter.next().unwrap()), vec!["genesis"]);
+        assert_eq!(memos(iter.next().unwrap()), vec!["transaction1"]);
+        assert_eq!(memos(iter.next().unwrap()), vec!["transaction2"]);
         assert!(iter.next().is_none());
     }
 
     #[test]
     fn test_iter_reverse() {
-        let mut blockchain = Blockchain::new(2);
+        let mut blockchain = Blockchain::new(2).unwrap();
         blockchain
-            .add_block(vec!["transaction1".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
             .unwrap();
         blockchain
-            .add_block(vec!["transaction2".to_string()])
+            .add_block(MINER, vec![Transaction::memo("transaction2").unwrap()])
             .unwrap();
 
         let mut iter = blockchain.iter_reverse();
+        assert_eq!(memos(iter.next().unwrap()), vec!["transaction2"]);
+        assert_eq!(memos(iter.next().unwrap()), vec!["transaction1"]);
+        assert_eq!(memos(iter.next().unwrap()), vec!["genesis"]);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_validate_valid_chain() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
+            .unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("transaction2").unwrap()])
+            .unwrap();
+        blockchain.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_tampered_transactions() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
+            .unwrap();
+        blockchain.chain[1].transactions[1].memo = "forged".to_string();
+        assert_eq!(
+            invalid_at(blockchain.validate()),
+            ValidationError {
+                height: 1,
+                reason: InvalidBlock::MerkleRootMismatch
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_broken_link() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("transaction1").unwrap()])
+            .unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("transaction2").unwrap()])
+            .unwrap();
+        blockchain.chain.remove(1);
         assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["transaction2".to_string()]
+            invalid_at(blockchain.validate()),
+            ValidationError {
+                height: 1,
+                reason: InvalidBlock::BrokenLink
+            }
         );
+    }
+
+    #[test]
+    fn test_validate_difficulty_mismatch() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.difficulty = 3;
         assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["transaction1".to_string()]
+            invalid_at(blockchain.validate()),
+            ValidationError {
+                height: 0,
+                reason: InvalidBlock::DifficultyMismatch {
+                    expected: compact_from_difficulty(3),
+                    found: compact_from_difficulty(2)
+                }
+            }
         );
+    }
+
+    #[test]
+    fn test_validate_empty_chain() {
+        let blockchain = Blockchain::empty(2, LedgerMode::Account, Consensus::default());
         assert_eq!(
-            iter.next().unwrap().transactions,
-            vec!["genesis".to_string()]
+            invalid_at(blockchain.validate()).reason,
+            InvalidBlock::MissingGenesis
         );
-        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_add_block_with_signed_transaction() {
+        let keypair = Keypair::generate();
+        let mut blockchain = Blockchain::with_allocations(2, &[(keypair.address(), 10)]).unwrap();
+        let recipient = Keypair::generate().address();
+        let mut tx = Transaction::new(&keypair.address(), &recipient, 10, 0).unwrap();
+        tx.sign(&keypair);
+
+        assert!(blockchain.add_block(MINER, vec![tx]).is_ok());
+        blockchain.validate().unwrap();
+    }
+
+    #[test]
+    fn test_add_block_rejects_invalid_signature() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        let keypair = Keypair::generate();
+        let recipient = Keypair::generate().address();
+        let mut tx = Transaction::new(&keypair.address(), &recipient, 10, 0).unwrap();
+        tx.sign(&keypair);
+        tx.amount = 1000;
+
+        assert!(blockchain.add_block(MINER, vec![tx]).is_err());
+        assert_eq!(blockchain.chain.len(), 1);
+    }
+
+    #[test]
+    fn test_add_block_rejects_invalid_recipient() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        let keypair = Keypair::generate();
+        let mut tx = Transaction::new(&keypair.address(), "bob", 10, 0).unwrap();
+        tx.sign(&keypair);
+
+        assert!(blockchain.add_block(MINER, vec![tx]).is_err());
+        assert_eq!(blockchain.chain.len(), 1);
+    }
+
+    #[test]
+    fn test_balances_follow_transfers() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut blockchain = Blockchain::with_allocations(2, &[(alice.address(), 100)]).unwrap();
+        let mut tx = Transaction::new(&alice.address(), &bob, 40, 0).unwrap();
+        tx.sign(&alice);
+
+        blockchain.add_block(MINER, vec![tx]).unwrap();
+
+        assert_eq!(blockchain.balance_of(&alice.address()), 60);
+        assert_eq!(blockchain.balance_of(&bob), 40);
+        assert_eq!(blockchain.nonce_of(&alice.address()), 1);
+    }
+
+    #[test]
+    fn test_overflowing_allocations_are_rejected() {
+        let allocations = [(MINER.to_string(), u64::MAX), (MINER.to_string(), 1)];
+        assert!(matches!(
+            rejected(Blockchain::with_allocations(2, &allocations)),
+            InvalidBlock::Ledger(_)
+        ));
+    }
+
+    #[test]
+    fn test_add_block_rejects_overdraft_and_replay() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut blockchain = Blockchain::with_allocations(2, &[(alice.address(), 50)]).unwrap();
+        let mut overdraft = Transaction::new(&alice.address(), &bob, 51, 0).unwrap();
+        overdraft.sign(&alice);
+        let mut tx = Transaction::new(&alice.address(), &bob, 10, 0).unwrap();
+        tx.sign(&alice);
+
+        assert!(blockchain.add_block(MINER, vec![overdraft]).is_err());
+        blockchain.add_block(MINER, vec![tx.clone()]).unwrap();
+        assert!(blockchain.add_block(MINER, vec![tx]).is_err());
+        assert_eq!(blockchain.chain.len(), 2);
+        assert_eq!(blockchain.balance_of(&alice.address()), 40);
+    }
+
+    #[test]
+    fn test_restore_and_rebuild_state() {
+        let alice = Keypair::generate();
+        let blockchain = Blockchain::with_allocations(2, &[(alice.address(), 10)]).unwrap();
+        let mut other = blockchain.clone();
+
+        let mut empty = Blockchain::new(2).unwrap();
+        assert!(!empty.restore_state(blockchain.state().clone()));
+        assert!(other.restore_state(blockchain.state().clone()));
+
+        other.state = WorldState::new();
+        other.rebuild_state().unwrap();
+        assert_eq!(other.balance_of(&alice.address()), 10);
+        empty.rebuild_state().unwrap();
+        assert_eq!(empty.balance_of(&alice.address()), 0);
+    }
+
+    #[test]
+    fn test_utxo_chain_transfers() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut blockchain =
+            Blockchain::with_ledger(2, LedgerMode::Utxo, &[(alice.address(), 50)]).unwrap();
+        let address = alice.address();
+        let (coin, _) = blockchain.utxo_set().unspent_for(&address).next().unwrap();
+        let outputs = vec![
+            TxOutput {
+                value: 20,
+                locking_key: bob.clone(),
+            },
+            TxOutput {
+                value: 30,
+                locking_key: alice.address(),
+            },
+        ];
+        let mut tx = Transaction::utxo(vec![coin.clone()], outputs).unwrap();
+        tx.sign_input(0, &alice);
+
+        blockchain.add_block(MINER, vec![tx.clone()]).unwrap();
+        assert_eq!(blockchain.balance_of(&bob), 20);
+        assert_eq!(blockchain.balance_of(&alice.address()), 30);
+
+        assert!(blockchain.add_block(MINER, vec![tx]).is_err());
+        assert!(
+            blockchain
+                .add_block(
+                    MINER,
+                    vec![Transaction::new(&alice.address(), &bob, 1, 0).unwrap()]
+                )
+                .is_err()
+        );
+        assert_eq!(blockchain.chain.len(), 2);
+
+        let mut replayed = blockchain.clone();
+        replayed.utxos = UtxoSet::new();
+        replayed.rebuild_state().unwrap();
+        assert_eq!(replayed.utxo_set(), blockchain.utxo_set());
+    }
+
+    #[test]
+    fn test_coinbase_pays_reward_and_fees() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut blockchain = Blockchain::with_allocations(2, &[(alice.address(), 100)]).unwrap();
+        blockchain.reward = RewardSchedule {
+            initial: 50,
+            halving_interval: 2,
+        };
+        let mut tx = Transaction::new(&alice.address(), &bob, 10, 0)
+            .unwrap()
+            .with_fee(3);
+        tx.sign(&alice);
+
+        blockchain.add_block(MINER, vec![tx]).unwrap();
+        blockchain.add_block(MINER, vec![]).unwrap();
+        assert!(blockchain.chain[1].transactions[0].is_coinbase());
+        assert_eq!(blockchain.balance_of(MINER), 53 + 25);
+        assert_eq!(blockchain.balance_of(&alice.address()), 87);
+        blockchain.validate().unwrap();
+        assert!(blockchain.add_block("miner", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_coinbase() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.add_block(MINER, vec![]).unwrap();
+        let prev_hash = blockchain.chain[0].hash;
+
+        let inflated = Transaction::coinbase(1, MINER, 51, LedgerMode::Account).unwrap();
+        blockchain.chain[1] = Block::new(prev_hash, vec![inflated], compact_from_difficulty(2))
+            .unwrap()
+            .mine(&blockchain.miner)
+            .unwrap();
+        assert_eq!(
+            invalid_at(blockchain.validate()).reason,
+            InvalidBlock::CoinbaseTooLarge {
+                allowed: 50,
+                found: 51
+            }
+        );
+
+        blockchain.chain[1] = Block::new(
+            prev_hash,
+            vec![Transaction::memo("no reward").unwrap()],
+            compact_from_difficulty(2),
+        )
+        .unwrap()
+        .mine(&blockchain.miner)
+        .unwrap();
+        assert_eq!(
+            invalid_at(blockchain.validate()).reason,
+            InvalidBlock::MissingCoinbase
+        );
+    }
+
+    #[test]
+    fn test_retarget_and_validate_expected_difficulty() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.consensus = Consensus::Work(ProofOfWork {
+            retarget: Retarget {
+                target_spacing: 1000,
+                rule: RetargetRule::Window { blocks: 2 },
+            },
+        });
+        blockchain.add_block(MINER, vec![]).unwrap();
+        // One interval far faster than 1000 seconds: the target shrinks by the maximum factor.
+        let bits = blockchain.next_bits();
+        assert_eq!(
+            difficulty_from_target(target_from_compact(bits).unwrap()),
+            4
+        );
+        blockchain.add_block(MINER, vec![]).unwrap();
+        assert_eq!(blockchain.chain[2].header.bits, bits);
+        blockchain.validate().unwrap();
+
+        blockchain.consensus = Consensus::Work(ProofOfWork {
+            retarget: Retarget {
+                target_spacing: 1000,
+                rule: RetargetRule::Fixed,
+            },
+        });
+        assert_eq!(
+            invalid_at(blockchain.validate()),
+            ValidationError {
+                height: 2,
+                reason: InvalidBlock::DifficultyMismatch {
+                    expected: compact_from_difficulty(2),
+                    found: bits
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn test_rejects_timestamps_too_far_ahead() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.consensus = Consensus::Work(ProofOfWork {
+            retarget: Retarget {
+                target_spacing: 1000,
+                rule: RetargetRule::MovingAverage { blocks: 2 },
+            },
+        });
+        let genesis = blockchain.chain[0].clone();
+        let at = |blockchain: &Blockchain, timestamp: u64| {
+            let mut block = block_on(blockchain, &genesis, 1, MINER, vec![]);
+            block.header.timestamp = timestamp;
+            block.mine(&blockchain.miner).unwrap()
+        };
+        let now = genesis.header.timestamp;
+
+        let ahead = at(&blockchain, now + MAX_FUTURE_DRIFT + 3600);
+        assert_eq!(
+            rejected(blockchain.submit_block(ahead)),
+            InvalidBlock::TimestampTooFarAhead
+        );
+        assert_eq!(blockchain.chain.len(), 1);
+
+        // Within the drift the block is accepted, and a later timestamp lowers the difficulty
+        // of the next block.
+        let mut honest = blockchain.clone();
+        honest.submit_block(at(&honest, now)).unwrap();
+        let within = at(&blockchain, now + MAX_FUTURE_DRIFT / 2);
+        assert_eq!(
+            blockchain.submit_block(within).unwrap(),
+            BlockStatus::Active
+        );
+        let difficulty = |chain: &Blockchain| {
+            difficulty_from_target(target_from_compact(chain.next_bits()).unwrap())
+        };
+        assert!(difficulty(&blockchain) < difficulty(&honest));
+    }
+
+    #[test]
+    fn test_submit_block_reorganizes_to_most_work() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.add_block(MINER, vec![]).unwrap();
+        let events = blockchain.subscribe();
+        let other = Keypair::generate().address();
+        let genesis = blockchain.chain[0].clone();
+        let a1 = blockchain.chain[1].clone();
+
+        let b1 = block_on(&blockchain, &genesis, 1, &other, vec![]);
+        assert_eq!(
+            blockchain.submit_block(b1.clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        assert_eq!(blockchain.chain[1].hash, a1.hash);
+        assert_eq!(events.try_iter().count(), 0);
+
+        let b2 = block_on(&blockchain, &b1, 2, &other, vec![]);
+        assert_eq!(
+            blockchain.submit_block(b2.clone()).unwrap(),
+            BlockStatus::Active
+        );
+        let hashes: Vec<_> = blockchain.iter().map(|block| block.hash).collect();
+        assert_eq!(hashes, vec![genesis.hash, b1.hash, b2.hash]);
+        assert_eq!(blockchain.balance_of(MINER), 0);
+        assert_eq!(blockchain.balance_of(&other), 100);
+        blockchain.validate().unwrap();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[0],
+            ChainEvent::BlockDisconnected { height: 1, block } if block.hash == a1.hash
+        ));
+        assert!(matches!(
+            &events[2],
+            ChainEvent::BlockConnected { height: 2, .. }
+        ));
+        assert!(matches!(
+            events[3],
+            ChainEvent::Reorganized {
+                fork_height: 0,
+                disconnected: 1,
+                connected: 2
+            }
+        ));
+        assert_eq!(blockchain.submit_block(a1).unwrap(), BlockStatus::Known);
+    }
+
+    #[test]
+    fn test_submit_block_rejects_invalid_blocks() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        let genesis = blockchain.chain[0].clone();
+        let mut orphan = block_on(&blockchain, &genesis, 1, MINER, vec![]);
+        orphan.header.prev_hash = Hash256([1; 32]);
+        assert_eq!(
+            rejected(blockchain.submit_block(orphan)),
+            InvalidBlock::UnknownParent
+        );
+
+        let mut wrong_bits = block_on(&blockchain, &genesis, 1, MINER, vec![]);
+        wrong_bits.header.bits = compact_from_difficulty(1);
+        assert!(matches!(
+            rejected(blockchain.submit_block(wrong_bits.clone())),
+            InvalidBlock::DifficultyMismatch { .. }
+        ));
+        assert!(!blockchain.contains_block(&wrong_bits.hash));
+
+        let keypair = Keypair::generate();
+        let mut tx = Transaction::new(&keypair.address(), "bob", 0, 0).unwrap();
+        tx.sign(&keypair);
+        let bad_recipient = block_on(&blockchain, &genesis, 1, MINER, vec![tx]);
+        assert_eq!(
+            rejected(blockchain.submit_block(bad_recipient)),
+            InvalidBlock::InvalidRecipient { index: 1 }
+        );
+        assert_eq!(blockchain.chain.len(), 1);
+    }
+
+    #[test]
+    fn test_failed_reorg_keeps_active_chain() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.add_block(MINER, vec![]).unwrap();
+        let genesis = blockchain.chain[0].clone();
+        let a1 = blockchain.chain[1].clone();
+        let broke = Keypair::generate();
+        let mut overdraft = Transaction::new(&broke.address(), MINER, 5, 0).unwrap();
+        overdraft.sign(&broke);
+
+        let b1 = block_on(&blockchain, &genesis, 1, MINER, vec![overdraft]);
+        assert_eq!(
+            blockchain.submit_block(b1.clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        let b2 = block_on(&blockchain, &b1, 2, MINER, vec![]);
+        assert!(matches!(
+            rejected(blockchain.submit_block(b2)),
+            InvalidBlock::Ledger(_)
+        ));
+        assert_eq!(blockchain.chain.len(), 2);
+        assert_eq!(blockchain.chain[1].hash, a1.hash);
+        assert_eq!(blockchain.balance_of(MINER), 50);
+    }
+
+    #[test]
+    fn test_submit_block_validates_stored_side_blocks() {
+        let mut blockchain = Blockchain::new(2).unwrap();
+        blockchain.add_block(MINER, vec![]).unwrap();
+        let genesis = blockchain.chain[0].clone();
+        let forged = Block::new(
+            genesis.hash,
+            vec![Transaction::coinbase(1, MINER, 1_000, LedgerMode::Account).unwrap()],
+            compact_from_difficulty(blockchain.difficulty),
+        )
+        .unwrap()
+        .mine(&blockchain.miner)
+        .unwrap();
+
+        // As loaded from a store: the side block is kept without being checked.
+        let mut blockchain = Blockchain::from_parts(
+            blockchain.difficulty,
+            LedgerMode::Account,
+            Consensus::default(),
+            blockchain.chain,
+            [forged.clone()],
+        );
+        blockchain.rebuild_state().unwrap();
+        let b2 = block_on(&blockchain, &forged, 2, MINER, vec![]);
+        assert!(matches!(
+            rejected(blockchain.submit_block(b2)),
+            InvalidBlock::CoinbaseTooLarge { .. }
+        ));
+        assert_eq!(blockchain.chain.len(), 2);
+        assert_eq!(blockchain.balance_of(MINER), 50);
+    }
+
+    #[test]
+    fn test_proof_of_authority_chain() {
+        let alice = Arc::new(Keypair::from_secret_bytes(&[1; 32]));
+        let bob = Arc::new(Keypair::from_secret_bytes(&[2; 32]));
+        let consensus = Consensus::Authority(ProofOfAuthority {
+            authorities: vec![alice.address(), bob.address()],
+        });
+        let mut blockchain =
+            Blockchain::with_consensus(2, LedgerMode::Account, consensus, &[]).unwrap();
+        assert!(blockchain.chain[0].seal.is_empty());
+        assert!(matches!(
+            blockchain.add_block(MINER, vec![]),
+            Err(Error::Consensus(SealError::MissingSigner))
+        ));
+
+        blockchain.signer = Some(Arc::clone(&alice));
+        blockchain.add_block(MINER, vec![]).unwrap();
+        let unsealed = blockchain.prepare_block(MINER, vec![]).unwrap();
+        assert!(matches!(
+            blockchain.seal(&unsealed, &CancelToken::new(), |_| {}),
+            Err(Error::Consensus(SealError::NotProposer { proposer })) if proposer == bob.address()
+        ));
+
+        blockchain.signer = Some(Arc::clone(&bob));
+        blockchain.add_block(MINER, vec![]).unwrap();
+        assert_eq!(seal_signer(&blockchain.chain[1]), Some(alice.address()));
+        assert_eq!(seal_signer(&blockchain.chain[2]), Some(bob.address()));
+        assert_eq!(blockchain.chain_work(), U256::from_u64(3));
+        blockchain.validate().unwrap();
+
+        // Alice re-signs the block that was Bob's turn.
+        let mut forged = blockchain.chain[2].clone();
+        forged.seal = alice.public_key();
+        forged.seal.extend(alice.sign(forged.hash.as_bytes()));
+        blockchain.chain[2] = forged;
+        assert_eq!(
+            invalid_at(blockchain.validate()).reason,
+            InvalidBlock::WrongSigner {
+                expected: bob.address(),
+                found: alice.address()
+            }
+        );
+        blockchain.chain[2].seal.clear();
+        assert_eq!(
+            invalid_at(blockchain.validate()).reason,
+            InvalidBlock::InvalidSeal
+        );
+    }
+
+    /// An engine outside the built-in ones: every block carries the same bits and the seal
+    /// `b"ok"`, and weighs one.
+    struct Stamped;
+
+    impl ConsensusEngine for Stamped {
+        fn next_bits(&self, _previous: &[Block], genesis_bits: u32) -> u32 {
+            genesis_bits
+        }
+
+        fn verify_seal(&self, _previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+            if block.seal == b"ok" {
+                Ok(())
+            } else {
+                Err(InvalidBlock::InvalidSeal)
+            }
+        }
+
+        fn seal(
+            &self,
+            _previous: &[Block],
+            block: &Block,
+            _sealer: &Sealer<'_>,
+            _progress: &mut dyn FnMut(&MiningStats),
+        ) -> Result<MiningOutcome, Error> {
+            let mut block = block.clone();
+            block.seal = b"ok".to_vec();
+            Ok(MiningOutcome::Mined {
+                block,
+                stats: MiningStats {
+                    hashes: 0,
+                    elapsed: Duration::ZERO,
+                },
+            })
+        }
+
+        fn block_work(&self, _block: &Block) -> U256 {
+            U256::from_u64(1)
+        }
+    }
+
+    #[test]
+    fn test_custom_consensus_engine() {
+        let mut blockchain =
+            Blockchain::with_consensus(2, LedgerMode::Account, Stamped, &[]).unwrap();
+        blockchain
+            .add_block(MINER, vec![Transaction::memo("block 1").unwrap()])
+            .unwrap();
+        assert_eq!(blockchain.chain[1].seal, b"ok");
+        assert_eq!(blockchain.chain_work(), U256::from_u64(2));
+        blockchain.validate().unwrap();
+
+        let mut unsealed = blockchain.prepare_block(MINER, vec![]).unwrap();
+        unsealed.seal = b"no".to_vec();
+        assert_eq!(
+            rejected(blockchain.submit_block(unsealed)),
+            InvalidBlock::InvalidSeal
+        );
+    }
+
+    #[test]
+    fn test_proof_of_stake_chain() {
+        let alice = Arc::new(Keypair::from_secret_bytes(&[1; 32]));
+        let bob = Arc::new(Keypair::from_secret_bytes(&[2; 32]));
+        let stake = ProofOfStake {
+            validators: vec![
+                Validator {
+                    address: alice.address(),
+                    stake: 3,
+                },
+                Validator {
+                    address: bob.address(),
+                    stake: 1,
+                },
+            ],
+        };
+        let mut blockchain = Blockchain::with_consensus(
+            2,
+            LedgerMode::Account,
+            Consensus::Stake(stake.clone()),
+            &[],
+        )
+        .unwrap();
+        for _ in 0..4 {
+            let proposer = stake
+                .proposer(blockchain.chain.last().unwrap().hash.as_bytes())
+                .unwrap();
+            let (signer, other) = if proposer == alice.address() {
+                (&alice, &bob)
+            } else {
+                (&bob, &alice)
+            };
+            blockchain.signer = Some(Arc::clone(other));
+            assert!(blockchain.add_block(MINER, vec![]).is_err());
+            blockchain.signer = Some(Arc::clone(signer));
+            blockchain.add_block(MINER, vec![]).unwrap();
+            assert_eq!(
+                seal_signer(blockchain.chain.last().unwrap()).as_deref(),
+                Some(proposer)
+            );
+        }
+        blockchain.validate().unwrap();
     }
 }
@@ -0,0 +1,191 @@
+pub mod poa;
+pub mod pos;
+pub mod pow;
+
+use super::block::Block;
+use super::miner::{CancelToken, Miner, MiningOutcome, MiningStats};
+use super::target::U256;
+use super::validation::InvalidBlock;
+use crate::error::Error;
+use crate::utils::crypto::{Keypair, address_from_public_key, verify_signature};
+use poa::ProofOfAuthority;
+use pos::ProofOfStake;
+use pow::ProofOfWork;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Instant;
+
+/// Length of a public key followed by an Ed25519 signature, the seal of a signed block.
+const SIGNED_SEAL_LEN: usize = 32 + 64;
+
+/// The rules that decide who may produce the next block and how it is sealed.
+///
+/// - `next_bits`: the `bits` the block following `previous` must carry; `genesis_bits` is the
+///   value of the genesis block.
+/// - `verify_seal`: checks the seal of `block`, the successor of `previous`. The block's
+///   structure has already been checked with `Block::verify`.
+/// - `seal`: produces a sealed copy of the unsealed `block` that follows `previous`, using the
+///   local resources in `sealer` and reporting progress while it runs. It fails with
+///   `Error::Consensus` if the node may not seal the block.
+/// - `block_work`: the weight `block` adds to its branch when choosing the active chain.
+pub trait ConsensusEngine {
+    fn next_bits(&self, previous: &[Block], genesis_bits: u32) -> u32;
+
+    fn verify_seal(&self, previous: &[Block], block: &Block) -> Result<(), InvalidBlock>;
+
+    fn seal(
+        &self,
+        previous: &[Block],
+        block: &Block,
+        sealer: &Sealer<'_>,
+        progress: &mut dyn FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error>;
+
+    fn block_work(&self, block: &Block) -> U256;
+}
+
+/// What the local node brings to sealing: threads for proof of work, a key for signed seals,
+/// and a token that aborts the attempt.
+pub struct Sealer<'a> {
+    pub miner: &'a Miner,
+    pub signer: Option<&'a Keypair>,
+    pub cancel: &'a CancelToken,
+}
+
+/// The reason the local node cannot seal a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    /// The engine signs blocks but no signing key was configured.
+    MissingSigner,
+    /// The configured key is not the one scheduled to seal this block.
+    NotProposer { proposer: String },
+    /// The engine has no authority or validator with stake to seal the block.
+    NoProposer,
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SealError::MissingSigner => write!(f, "no signing key is configured"),
+            SealError::NotProposer { proposer } => {
+                write!(f, "the next block must be sealed by {}", proposer)
+            }
+            SealError::NoProposer => write!(f, "no authority or validator can seal the block"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+/// The configuration of a built-in consensus engine, as stored with a chain.
+///
+/// Each variant wraps an engine and `Consensus` implements `ConsensusEngine` by delegating to
+/// it. It is the default engine of `Blockchain`, which accepts any `ConsensusEngine`; a chain
+/// kept by `BlockchainManager` uses `Consensus` so that its engine can be saved with it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Consensus {
+    Work(ProofOfWork),
+    Authority(ProofOfAuthority),
+    Stake(ProofOfStake),
+}
+
+impl Default for Consensus {
+    fn default() -> Self {
+        Consensus::Work(ProofOfWork::default())
+    }
+}
+
+impl Consensus {
+    fn engine(&self) -> &dyn ConsensusEngine {
+        match self {
+            Consensus::Work(engine) => engine,
+            Consensus::Authority(engine) => engine,
+            Consensus::Stake(engine) => engine,
+        }
+    }
+}
+
+impl ConsensusEngine for Consensus {
+    fn next_bits(&self, previous: &[Block], genesis_bits: u32) -> u32 {
+        self.engine().next_bits(previous, genesis_bits)
+    }
+
+    fn verify_seal(&self, previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+        self.engine().verify_seal(previous, block)
+    }
+
+    fn seal(
+        &self,
+        previous: &[Block],
+        block: &Block,
+        sealer: &Sealer<'_>,
+        progress: &mut dyn FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        self.engine().seal(previous, block, sealer, progress)
+    }
+
+    fn block_work(&self, block: &Block) -> U256 {
+        self.engine().block_work(block)
+    }
+}
+
+/// Returns the address that signed `block`, if its seal is a valid signature of its hash.
+pub fn seal_signer(block: &Block) -> Option<String> {
+    if block.seal.len() != SIGNED_SEAL_LEN {
+        return None;
+    }
+    let (public_key, signature) = block.seal.split_at(32);
+    verify_signature(public_key, block.hash.as_bytes(), signature)
+        .then(|| address_from_public_key(public_key))
+}
+
+/// Checks that `block`, at `height`, is signed by `proposer`. The genesis block carries no
+/// seal, and a later block without a proposer cannot be valid.
+fn verify_signed(block: &Block, height: usize, proposer: Option<&str>) -> Result<(), InvalidBlock> {
+    if height == 0 {
+        if block.seal.is_empty() {
+            return Ok(());
+        }
+        return Err(InvalidBlock::InvalidSeal);
+    }
+    let expected = proposer.ok_or(InvalidBlock::InvalidSeal)?;
+    let found = seal_signer(block).ok_or(InvalidBlock::InvalidSeal)?;
+    if found != expected {
+        return Err(InvalidBlock::WrongSigner {
+            expected: expected.to_string(),
+            found,
+        });
+    }
+    Ok(())
+}
+
+/// Signs `block`, at `height`, with the sealer's key if it belongs to `proposer`. The genesis
+/// block is returned unsealed.
+fn seal_signed(
+    block: &Block,
+    height: usize,
+    proposer: Option<&str>,
+    sealer: &Sealer<'_>,
+) -> Result<MiningOutcome, Error> {
+    let started = Instant::now();
+    let mut block = block.clone();
+    if height > 0 {
+        let proposer = proposer.ok_or(SealError::NoProposer)?;
+        let signer = sealer.signer.ok_or(SealError::MissingSigner)?;
+        if signer.address() != proposer {
+            return Err(SealError::NotProposer {
+                proposer: proposer.to_string(),
+            }
+            .into());
+        }
+        block.seal = signer.public_key();
+        block.seal.extend(signer.sign(block.hash.as_bytes()));
+    }
+    Ok(MiningOutcome::Mined {
+        block,
+        stats: MiningStats {
+            hashes: 0,
+            elapsed: started.elapsed(),
+        },
+    })
+}
@@ -0,0 +1,67 @@
+use super::{ConsensusEngine, Sealer, seal_signed, verify_signed};
+use crate::core::block::Block;
+use crate::core::miner::{MiningOutcome, MiningStats};
+use crate::core::target::U256;
+use crate::core::validation::InvalidBlock;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// Proof of authority: the listed addresses take turns sealing blocks, starting with the first
+/// authority at height 1, by signing the block hash. Every block weighs the same, so the
+/// longest branch wins and `bits` keeps the genesis value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProofOfAuthority {
+    pub authorities: Vec<String>,
+}
+
+impl ProofOfAuthority {
+    /// Returns the authority scheduled to seal the block at `height`, or `None` for the
+    /// genesis block or an empty authority list.
+    pub fn proposer(&self, height: usize) -> Option<&str> {
+        let index = height.checked_sub(1)? % self.authorities.len().max(1);
+        self.authorities.get(index).map(String::as_str)
+    }
+}
+
+impl ConsensusEngine for ProofOfAuthority {
+    fn next_bits(&self, _previous: &[Block], genesis_bits: u32) -> u32 {
+        genesis_bits
+    }
+
+    fn verify_seal(&self, previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+        let height = previous.len();
+        verify_signed(block, height, self.proposer(height))
+    }
+
+    fn seal(
+        &self,
+        previous: &[Block],
+        block: &Block,
+        sealer: &Sealer<'_>,
+        _progress: &mut dyn FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        let height = previous.len();
+        seal_signed(block, height, self.proposer(height), sealer)
+    }
+
+    fn block_work(&self, _block: &Block) -> U256 {
+        U256::ONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorities_take_turns() {
+        let poa = ProofOfAuthority {
+            authorities: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        };
+        assert_eq!(poa.proposer(0), None);
+        assert_eq!(poa.proposer(1), Some("a"));
+        assert_eq!(poa.proposer(3), Some("c"));
+        assert_eq!(poa.proposer(4), Some("a"));
+        assert_eq!(ProofOfAuthority::default().proposer(1), None);
+    }
+}
@@ -0,0 +1,119 @@
+use super::{ConsensusEngine, Sealer, seal_signed, verify_signed};
+use crate::core::block::Block;
+use crate::core::miner::{MiningOutcome, MiningStats};
+use crate::core::target::U256;
+use crate::core::validation::InvalidBlock;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A validator of a `ProofOfStake` chain and the stake it is weighted by.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Validator {
+    pub address: String,
+    pub stake: u64,
+}
+
+/// Proof of stake: the block after each parent is sealed by one validator, drawn with
+/// probability proportional to its stake using the parent hash as the random seed. The chosen
+/// validator signs the block hash. Every block weighs the same, so the longest branch wins and
+/// `bits` keeps the genesis value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProofOfStake {
+    pub validators: Vec<Validator>,
+}
+
+impl ProofOfStake {
+    /// Returns the validator chosen to seal the block following the block `parent_hash`, or
+    /// `None` if no validator has any stake.
+    pub fn proposer(&self, parent_hash: &[u8]) -> Option<&str> {
+        let total: u128 = self.validators.iter().map(|v| u128::from(v.stake)).sum();
+        if total == 0 {
+            return None;
+        }
+        let digest = Sha256::digest(parent_hash);
+        let seed = u128::from_be_bytes(digest[..16].try_into().expect("digest is 32 bytes"));
+        let mut point = seed % total;
+        for validator in &self.validators {
+            let stake = u128::from(validator.stake);
+            if point < stake {
+                return Some(&validator.address);
+            }
+            point -= stake;
+        }
+        None
+    }
+
+    fn proposer_after(&self, previous: &[Block]) -> Option<&str> {
+        previous
+            .last()
+            .and_then(|parent| self.proposer(parent.hash.as_bytes()))
+    }
+}
+
+impl ConsensusEngine for ProofOfStake {
+    fn next_bits(&self, _previous: &[Block], genesis_bits: u32) -> u32 {
+        genesis_bits
+    }
+
+    fn verify_seal(&self, previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+        verify_signed(block, previous.len(), self.proposer_after(previous))
+    }
+
+    fn seal(
+        &self,
+        previous: &[Block],
+        block: &Block,
+        sealer: &Sealer<'_>,
+        _progress: &mut dyn FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        seal_signed(block, previous.len(), self.proposer_after(previous), sealer)
+    }
+
+    fn block_work(&self, _block: &Block) -> U256 {
+        U256::ONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(address: &str, stake: u64) -> Validator {
+        Validator {
+            address: address.to_string(),
+            stake,
+        }
+    }
+
+    #[test]
+    fn test_proposer_weighted_by_stake() {
+        let pos = ProofOfStake {
+            validators: vec![
+                validator("light", 1),
+                validator("none", 0),
+                validator("heavy", 3),
+            ],
+        };
+        let picks: Vec<_> = (0u32..1000)
+            .map(|i| pos.proposer(&i.to_le_bytes()).unwrap())
+            .collect();
+        let heavy = picks.iter().filter(|&&address| address == "heavy").count();
+
+        assert!(!picks.contains(&"none"));
+        assert!(
+            (650..850).contains(&heavy),
+            "heavy proposed {} of 1000",
+            heavy
+        );
+        assert_eq!(pos.proposer(b"parent"), pos.proposer(b"parent"));
+    }
+
+    #[test]
+    fn test_no_stake_no_proposer() {
+        let pos = ProofOfStake {
+            validators: vec![validator("idle", 0)],
+        };
+        assert_eq!(pos.proposer(b"parent"), None);
+    }
+}
@@ -0,0 +1,43 @@
+use super::{ConsensusEngine, Sealer};
+use crate::core::block::Block;
+use crate::core::difficulty::Retarget;
+use crate::core::miner::{MiningOutcome, MiningStats};
+use crate::core::target::U256;
+use crate::core::validation::InvalidBlock;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// Proof of work: a block is sealed by finding a nonce that puts its hash at or below the
+/// target in `bits`, which `retarget` adjusts toward the target block spacing. Branches are
+/// weighed by the expected number of hashes behind them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProofOfWork {
+    pub retarget: Retarget,
+}
+
+impl ConsensusEngine for ProofOfWork {
+    fn next_bits(&self, previous: &[Block], genesis_bits: u32) -> u32 {
+        self.retarget.next_bits(previous, genesis_bits)
+    }
+
+    fn verify_seal(&self, _previous: &[Block], block: &Block) -> Result<(), InvalidBlock> {
+        if !block.seal.is_empty() {
+            return Err(InvalidBlock::InvalidSeal);
+        }
+        block.check_work()
+    }
+
+    fn seal(
+        &self,
+        _previous: &[Block],
+        block: &Block,
+        sealer: &Sealer<'_>,
+        progress: &mut dyn FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        sealer.miner.mine(block, sealer.cancel, progress)
+    }
+
+    fn block_work(&self, block: &Block) -> U256 {
+        block.work()
+    }
+}
@@ -0,0 +1,189 @@
+use super::block::Block;
+use super::target::{POW_LIMIT, compact_from_target, target_from_compact};
+use serde::{Deserialize, Serialize};
+
+/// The largest factor by which a single retarget may scale the target, either way.
+const MAX_ADJUSTMENT_FACTOR: u64 = 4;
+
+/// When the target of the next block is recomputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RetargetRule {
+    /// Every block keeps the target of the genesis block.
+    Fixed,
+    /// Bitcoin-style: at every height that is a multiple of `blocks`, the time taken by the
+    /// last `blocks` block intervals is compared with the target. Other blocks keep the
+    /// target of their predecessor.
+    Window { blocks: usize },
+    /// At every height, the average spacing of the last `blocks` blocks is compared with the
+    /// target.
+    MovingAverage { blocks: usize },
+}
+
+/// Adjusts the target of each block toward `target_spacing` seconds between blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Retarget {
+    pub target_spacing: u64,
+    pub rule: RetargetRule,
+}
+
+impl Default for Retarget {
+    fn default() -> Self {
+        Self {
+            target_spacing: 10,
+            rule: RetargetRule::Window { blocks: 10 },
+        }
+    }
+}
+
+/// # Methods
+///
+/// - `next_bits(&self, previous: &[Block], initial: u32) -> u32`: Returns the compact target
+///   required of the block that follows `previous`. The genesis block uses `initial`.
+///
+/// A retarget measures the timestamps of the last `blocks` intervals and multiplies the previous
+/// target by observed over expected time. Observed time is clamped to within a factor of four of
+/// the expected time, and the target never exceeds `POW_LIMIT`.
+impl Retarget {
+    pub fn next_bits(&self, previous: &[Block], initial: u32) -> u32 {
+        let Some(last) = previous.last() else {
+            return initial;
+        };
+        let height = previous.len();
+        let blocks = match self.rule {
+            RetargetRule::Fixed => return initial,
+            RetargetRule::Window { blocks } if !height.is_multiple_of(blocks.max(1)) => {
+                return last.header.bits;
+            }
+            RetargetRule::Window { blocks } | RetargetRule::MovingAverage { blocks } => {
+                blocks.max(1)
+            }
+        };
+        let start = height.saturating_sub(blocks + 1);
+        let intervals = (height - 1 - start) as u64;
+        let Some(target) = target_from_compact(last.header.bits) else {
+            return last.header.bits;
+        };
+        if intervals == 0 {
+            return last.header.bits;
+        }
+        let expected = self
+            .target_spacing
+            .saturating_mul(intervals)
+            .clamp(MAX_ADJUSTMENT_FACTOR, u64::MAX / MAX_ADJUSTMENT_FACTOR);
+        let observed = last
+            .header
+            .timestamp
+            .saturating_sub(previous[start].header.timestamp)
+            .clamp(
+                expected / MAX_ADJUSTMENT_FACTOR,
+                expected * MAX_ADJUSTMENT_FACTOR,
+            );
+        let next = target
+            .mul_div(observed, expected)
+            .map_or(POW_LIMIT, |next| next.min(POW_LIMIT));
+        compact_from_target(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::target::{U256, compact_from_difficulty};
+    use crate::core::transaction::Transaction;
+    use crate::utils::hash::Hash256;
+
+    const BITS: u32 = 0x1f00_ffff;
+
+    /// Builds unmined blocks with the given timestamps and compact target; only headers are
+    /// read.
+    fn blocks(timestamps: &[u64], bits: u32) -> Vec<Block> {
+        timestamps
+            .iter()
+            .map(|&timestamp| {
+                let mut block = Block::new(
+                    Hash256::ZERO,
+                    vec![Transaction::memo("t").unwrap()],
+                    compact_from_difficulty(0),
+                )
+                .unwrap();
+                block.header.timestamp = timestamp;
+                block.header.bits = bits;
+                block
+            })
+            .collect()
+    }
+
+    fn retarget(rule: RetargetRule) -> Retarget {
+        Retarget {
+            target_spacing: 10,
+            rule,
+        }
+    }
+
+    fn scaled(bits: u32, mul: u64, div: u64) -> u32 {
+        compact_from_target(
+            target_from_compact(bits)
+                .unwrap()
+                .mul_div(mul, div)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_fixed_and_genesis() {
+        let fixed = retarget(RetargetRule::Fixed);
+        assert_eq!(fixed.next_bits(&[], BITS), BITS);
+        assert_eq!(
+            fixed.next_bits(&blocks(&[0, 1, 2], 0x1e00_ffff), BITS),
+            BITS
+        );
+    }
+
+    #[test]
+    fn test_window_retargets_only_at_boundaries() {
+        let window = retarget(RetargetRule::Window { blocks: 4 });
+        // Three blocks one second apart: not a boundary.
+        assert_eq!(window.next_bits(&blocks(&[0, 1, 2], BITS), BITS), BITS);
+        // Four blocks, three intervals of one second instead of ten: observed time is clamped
+        // to a quarter of the expected thirty seconds.
+        assert_eq!(
+            window.next_bits(&blocks(&[0, 1, 2, 3], BITS), BITS),
+            scaled(BITS, 7, 30)
+        );
+        // Four blocks, intervals of twenty seconds: half the rate, twice the target.
+        assert_eq!(
+            window.next_bits(&blocks(&[0, 20, 40, 60], BITS), BITS),
+            scaled(BITS, 2, 1)
+        );
+        // On target: unchanged.
+        assert_eq!(
+            window.next_bits(&blocks(&[0, 10, 20, 30], BITS), BITS),
+            BITS
+        );
+    }
+
+    #[test]
+    fn test_moving_average_adjusts_every_block() {
+        let average = retarget(RetargetRule::MovingAverage { blocks: 2 });
+        // Only the last two intervals count: 30 seconds for an expected 20.
+        assert_eq!(
+            average.next_bits(&blocks(&[0, 1, 11, 31], BITS), BITS),
+            scaled(BITS, 3, 2)
+        );
+        // Two seconds for an expected twenty: clamped to a quarter.
+        assert_eq!(
+            average.next_bits(&blocks(&[0, 1, 21, 22, 23], BITS), BITS),
+            scaled(BITS, 1, 4)
+        );
+        assert_eq!(average.next_bits(&blocks(&[0], BITS), BITS), BITS);
+    }
+
+    #[test]
+    fn test_target_capped_at_pow_limit() {
+        let average = retarget(RetargetRule::MovingAverage { blocks: 1 });
+        let easiest = compact_from_target(POW_LIMIT);
+        let next = average.next_bits(&blocks(&[0, 1000], easiest), easiest);
+        assert!(target_from_compact(next).unwrap() <= POW_LIMIT);
+        assert!(target_from_compact(next).unwrap() > U256::ZERO);
+    }
+}
@@ -0,0 +1,47 @@
+use super::block::Block;
+use std::fmt;
+
+/// A change to the active chain of a `Blockchain`, delivered to its subscribers.
+///
+/// A reorganization is reported as one `BlockDisconnected` per abandoned block, from the old tip
+/// down to the fork point, followed by one `BlockConnected` per block of the new branch and a
+/// final `Reorganized` summary. Disconnected blocks carry their transactions so consumers such
+/// as the mempool can take them back.
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// `block` became the active block at `height`.
+    BlockConnected { height: usize, block: Block },
+    /// `block` was removed from the active chain, where it was at `height`.
+    BlockDisconnected { height: usize, block: Block },
+    /// The active chain switched branches at `fork_height`, the height of the last block both
+    /// branches share.
+    Reorganized {
+        fork_height: usize,
+        disconnected: usize,
+        connected: usize,
+    },
+}
+
+impl fmt::Display for ChainEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainEvent::BlockConnected { height, block } => {
+                write!(f, "block {} connected at height {}", block.hash, height)
+            }
+            ChainEvent::BlockDisconnected { height, block } => write!(
+                f,
+                "block {} disconnected from height {}",
+                block.hash, height
+            ),
+            ChainEvent::Reorganized {
+                fork_height,
+                disconnected,
+                connected,
+            } => write!(
+                f,
+                "reorganized at height {}: {} blocks disconnected, {} connected",
+                fork_height, disconnected, connected
+            ),
+        }
+    }
+}
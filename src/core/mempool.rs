@@ -0,0 +1,412 @@
+use super::blockchain::{Blockchain, PendingLedger};
+use super::consensus::ConsensusEngine;
+use super::transaction::Transaction;
+use super::validation::InvalidBlock;
+use crate::error::Error;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Default limit on the total serialized size of the pending transactions.
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+/// Default limit on the serialized size of the transactions in a block template.
+pub const DEFAULT_MAX_BLOCK_BYTES: usize = 64 * 1024;
+
+/// The reason a transaction was not accepted into the mempool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolError {
+    /// A transaction with the same ID is already pending.
+    Duplicate,
+    /// Coinbase transactions are created by the miner and cannot be submitted.
+    Coinbase,
+    /// The transaction cannot follow the chain and the transactions already pending.
+    Invalid(InvalidBlock),
+    /// The transaction does not fit, either on its own or because the pool is full of
+    /// transactions paying a higher fee rate.
+    Full,
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::Duplicate => write!(f, "transaction is already pending"),
+            MempoolError::Coinbase => write!(f, "coinbase transactions cannot be submitted"),
+            MempoolError::Invalid(err) => write!(f, "transaction rejected: {}", err),
+            MempoolError::Full => write!(f, "mempool is full and the fee rate is too low"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MempoolError::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: Vec<u8>,
+    size: usize,
+    tx: Transaction,
+}
+
+/// Orders entries by fee per serialized byte.
+fn fee_rate(a: &Entry, b: &Entry) -> Ordering {
+    (a.tx.fee as u128 * b.size as u128).cmp(&(b.tx.fee as u128 * a.size as u128))
+}
+
+pub struct Mempool {
+    entries: Vec<Entry>,
+    ids: BTreeSet<Vec<u8>>,
+    max_bytes: usize,
+    bytes: usize,
+    /// The ledger of the chain the entries were last checked against, with every entry
+    /// applied, or `None` once an entry has been removed.
+    ledger: Option<PendingLedger>,
+}
+
+/// Transactions waiting to be mined.
+///
+/// Entries are kept in arrival order and, taken in that order, always apply on top of the
+/// chain they were last checked against, so a sender may queue several nonces and a UTXO
+/// transaction may spend the outputs of a pending one. The pool keeps the ledger state of
+/// that chain with every entry applied, so a new transaction is checked against it alone,
+/// and the entries are only checked again when the tip of the chain changes or an entry is
+/// evicted. The total serialized size is bounded by `max_bytes`; when it is exceeded the
+/// entries with the lowest fee rate are evicted.
+///
+/// # Methods
+///
+/// - `new(max_bytes: usize) -> Self`: Creates an empty pool holding at most `max_bytes` of
+///   serialized transactions.
+///
+/// - `add(&mut self, tx: Transaction, chain: &Blockchain) -> Result<(), Error>`: Prunes the
+///   pool against `chain`, then accepts `tx` if it is not already pending, is not a coinbase
+///   and is valid after the pending transactions. A refused transaction is reported as
+///   `Error::Mempool`.
+///
+/// - `prune(&mut self, chain: &Blockchain)`: Drops the entries that no longer apply on top of
+///   `chain`, such as the transactions it has just mined and the ones conflicting with them.
+///
+/// - `block_template(&self, chain: &Blockchain, max_block_bytes: usize) -> Vec<Transaction>`:
+///   Selects pending transactions by descending fee rate whose serialized sizes add up to at
+///   most `max_block_bytes`. A transaction whose predecessor (an earlier nonce or a spent
+///   pending output) pays a lower rate is selected right after it. The coinbase is left to
+///   `Blockchain::add_block`.
+///
+/// - `next_nonce(&self, chain: &Blockchain, address: &str) -> u64`: Returns the nonce the next
+///   transaction from `address` should carry, counting pending transactions.
+impl Mempool {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            ids: BTreeSet::new(),
+            max_bytes,
+            bytes: 0,
+            ledger: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total serialized size of the pending transactions.
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn contains(&self, id: &[u8]) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Iterates over the pending transactions in arrival order.
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.entries.iter().map(|entry| &entry.tx)
+    }
+
+    pub fn add(
+        &mut self,
+        tx: Transaction,
+        chain: &Blockchain<impl ConsensusEngine>,
+    ) -> Result<(), Error> {
+        self.prune(chain);
+        let id = tx.id();
+        if self.ids.contains(&id) {
+            return Err(MempoolError::Duplicate.into());
+        }
+        if tx.is_coinbase() {
+            return Err(MempoolError::Coinbase.into());
+        }
+        let size = tx.size();
+        if size > self.max_bytes {
+            return Err(MempoolError::Full.into());
+        }
+        self.ledger
+            .as_mut()
+            .expect("a pruned pool has a ledger")
+            .push(&tx)
+            .map_err(MempoolError::Invalid)?;
+
+        self.insert(Entry {
+            id: id.clone(),
+            size,
+            tx,
+        });
+        let mut evicted = false;
+        while self.bytes > self.max_bytes {
+            let lowest = self
+                .entries
+                .iter()
+                .enumerate()
+                .rev()
+                .min_by(|(_, a), (_, b)| fee_rate(a, b))
+                .map(|(index, _)| index)
+                .expect("an over-full pool has entries");
+            let entry = self.remove(lowest);
+            if entry.id == id {
+                return Err(MempoolError::Full.into());
+            }
+            evicted = true;
+        }
+        if evicted {
+            // Evicting a transaction invalidates the pending ones that depended on it.
+            self.prune(chain);
+            if !self.contains(&id) {
+                return Err(MempoolError::Full.into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn prune(&mut self, chain: &Blockchain<impl ConsensusEngine>) {
+        let tip = chain.get_last_block().map(|block| block.hash);
+        if self
+            .ledger
+            .as_ref()
+            .is_some_and(|ledger| ledger.tip() == tip)
+        {
+            return;
+        }
+        let mut ledger = chain.pending_ledger();
+        self.entries.retain(|entry| ledger.push(&entry.tx).is_ok());
+        self.ids = self.entries.iter().map(|entry| entry.id.clone()).collect();
+        self.bytes = self.entries.iter().map(|entry| entry.size).sum();
+        self.ledger = Some(ledger);
+    }
+
+    pub fn block_template(
+        &self,
+        chain: &Blockchain<impl ConsensusEngine>,
+        max_block_bytes: usize,
+    ) -> Vec<Transaction> {
+        let mut candidates: Vec<&Entry> = self.entries.iter().collect();
+        candidates.sort_by(|a, b| fee_rate(b, a));
+        let mut ledger = chain.pending_ledger();
+        let mut selected = Vec::new();
+        let mut bytes = 0;
+        loop {
+            let mut progress = false;
+            candidates.retain(|entry| {
+                if bytes + entry.size > max_block_bytes {
+                    return false;
+                }
+                if ledger.push(&entry.tx).is_ok() {
+                    selected.push(entry.tx.clone());
+                    bytes += entry.size;
+                    progress = true;
+                    false
+                } else {
+                    true
+                }
+            });
+            if !progress {
+                return selected;
+            }
+        }
+    }
+
+    pub fn next_nonce(&self, chain: &Blockchain<impl ConsensusEngine>, address: &str) -> u64 {
+        let pending = self
+            .transactions()
+            .filter(|tx| tx.sender == address)
+            .count();
+        chain.nonce_of(address) + pending as u64
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        self.bytes += entry.size;
+        self.ids.insert(entry.id.clone());
+        self.entries.push(entry);
+    }
+
+    fn remove(&mut self, index: usize) -> Entry {
+        self.ledger = None;
+        let entry = self.entries.remove(index);
+        self.bytes -= entry.size;
+        self.ids.remove(&entry.id);
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::utxo::{LedgerMode, TxOutput};
+    use crate::utils::crypto::Keypair;
+
+    const MINER: &str = "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450";
+
+    fn transfer(from: &Keypair, to: &str, amount: u64, fee: u64, nonce: u64) -> Transaction {
+        let mut tx = Transaction::new(&from.address(), to, amount, nonce)
+            .unwrap()
+            .with_fee(fee);
+        tx.sign(from);
+        tx
+    }
+
+    /// Returns the reason a transaction is expected to be refused for.
+    fn refused(result: Result<(), Error>) -> MempoolError {
+        match result {
+            Err(Error::Mempool(err)) => err,
+            other => panic!("expected the mempool to refuse, got {:?}", other),
+        }
+    }
+
+    fn funded(keys: &[&Keypair]) -> Blockchain {
+        let allocations: Vec<(String, u64)> = keys.iter().map(|key| (key.address(), 100)).collect();
+        Blockchain::with_allocations(1, &allocations).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_duplicates_coinbase_and_invalid() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let chain = funded(&[&alice]);
+        let mut pool = Mempool::new(DEFAULT_MAX_BYTES);
+        let tx = transfer(&alice, &bob, 10, 1, 0);
+
+        pool.add(tx.clone(), &chain).unwrap();
+        assert_eq!(
+            refused(pool.add(tx.clone(), &chain)),
+            MempoolError::Duplicate
+        );
+        assert!(pool.contains(&tx.id()));
+        let coinbase = Transaction::coinbase(1, MINER, 50, LedgerMode::Account).unwrap();
+        assert_eq!(refused(pool.add(coinbase, &chain)), MempoolError::Coinbase);
+        let mut forged = transfer(&alice, &bob, 10, 1, 1);
+        forged.amount = 20;
+        assert!(matches!(
+            refused(pool.add(forged, &chain)),
+            MempoolError::Invalid(InvalidBlock::InvalidSignature { .. })
+        ));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_validates_against_pending_transactions() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let chain = funded(&[&alice]);
+        let mut pool = Mempool::new(DEFAULT_MAX_BYTES);
+
+        pool.add(transfer(&alice, &bob, 60, 0, 0), &chain).unwrap();
+        assert_eq!(pool.next_nonce(&chain, &alice.address()), 1);
+        assert!(matches!(
+            refused(pool.add(transfer(&alice, &bob, 60, 0, 1), &chain)),
+            MempoolError::Invalid(InvalidBlock::Ledger(_))
+        ));
+        pool.add(transfer(&alice, &bob, 40, 0, 1), &chain).unwrap();
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_template_orders_by_fee_rate_and_respects_nonces() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let carol = Keypair::generate().address();
+        let chain = funded(&[&alice, &bob]);
+        let mut pool = Mempool::new(DEFAULT_MAX_BYTES);
+        let low = transfer(&alice, &carol, 1, 1, 0);
+        let high = transfer(&alice, &carol, 1, 10, 1);
+        let middle = transfer(&bob, &carol, 1, 5, 0);
+        pool.add(low.clone(), &chain).unwrap();
+        pool.add(high.clone(), &chain).unwrap();
+        pool.add(middle.clone(), &chain).unwrap();
+
+        let template = pool.block_template(&chain, DEFAULT_MAX_BLOCK_BYTES);
+        assert_eq!(template, vec![middle.clone(), low.clone(), high]);
+
+        let capped = pool.block_template(&chain, low.size() + middle.size());
+        assert_eq!(capped, vec![middle, low]);
+    }
+
+    #[test]
+    fn test_evicts_lowest_fee_rate() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let carol = Keypair::generate();
+        let dave = Keypair::generate().address();
+        let chain = funded(&[&alice, &bob, &carol]);
+        let cheap = transfer(&alice, &dave, 1, 1, 0);
+        let mut pool = Mempool::new(cheap.size() * 2);
+
+        pool.add(cheap.clone(), &chain).unwrap();
+        pool.add(transfer(&bob, &dave, 1, 5, 0), &chain).unwrap();
+        pool.add(transfer(&carol, &dave, 1, 3, 0), &chain).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&cheap.id()));
+        assert!(pool.size_bytes() <= cheap.size() * 2);
+
+        let cheaper = transfer(&alice, &dave, 1, 0, 0);
+        assert_eq!(refused(pool.add(cheaper, &chain)), MempoolError::Full);
+    }
+
+    #[test]
+    fn test_prune_drops_mined_transactions() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut chain = funded(&[&alice]);
+        let mut pool = Mempool::new(DEFAULT_MAX_BYTES);
+        pool.add(transfer(&alice, &bob, 10, 1, 0), &chain).unwrap();
+        pool.add(transfer(&alice, &bob, 10, 1, 1), &chain).unwrap();
+
+        let template = pool.block_template(&chain, DEFAULT_MAX_BLOCK_BYTES);
+        chain.add_block(MINER, template[..1].to_vec()).unwrap();
+        pool.prune(&chain);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.transactions().next().unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn test_rejects_utxo_double_spend_of_pending_output() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let chain = Blockchain::with_ledger(1, LedgerMode::Utxo, &[(alice.address(), 50)]).unwrap();
+        let address = alice.address();
+        let (coin, _) = chain.utxo_set().unspent_for(&address).next().unwrap();
+        let spend = |to: &str| {
+            let output = TxOutput {
+                value: 50,
+                locking_key: to.to_string(),
+            };
+            let mut tx = Transaction::utxo(vec![coin.clone()], vec![output]).unwrap();
+            tx.sign_input(0, &alice);
+            tx
+        };
+        let mut pool = Mempool::new(DEFAULT_MAX_BYTES);
+
+        pool.add(spend(&bob), &chain).unwrap();
+        assert!(matches!(
+            refused(pool.add(spend(MINER), &chain)),
+            MempoolError::Invalid(InvalidBlock::Utxo(_))
+        ));
+    }
+}
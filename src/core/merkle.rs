@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Prefixes for leaves and interior nodes so that a node can never be mistaken for a leaf.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Proof that the leaf at `index` of a tree of `leaf_count` leaves is included under a Merkle
+/// root.
+///
+/// `path` lists the sibling hashes from the leaf level up to (but excluding) the root. The
+/// side of each sibling, and the levels at which the node has none, follow from `index` and
+/// `leaf_count`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    pub path: Vec<Vec<u8>>,
+}
+
+fn leaf_hash(leaf: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(leaf);
+    hasher.finalize().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Computes the Merkle root of the given leaf hashes, which are the SHA-256 transaction IDs.
+///
+/// Each leaf is hashed again with its own prefix before it enters the tree. An odd node at the end of a level is promoted unchanged rather than duplicated, so
+/// appending a copy of the last leaf always changes the root. An empty list yields 32 zero
+/// bytes.
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    if leaves.is_empty() {
+        return vec![0u8; 32];
+    }
+    let mut level: Vec<_> = leaves.iter().map(|leaf| leaf_hash(leaf)).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// Represents an inclusion proof for a single leaf.
+///
+/// # Methods
+///
+/// - `build(leaves: &[Vec<u8>], index: usize) -> Option<Self>`: Builds the proof for the leaf
+///   at `index`, or `None` if the index is out of range.
+///
+/// - `verify(&self, leaf: &[u8], root: &[u8]) -> bool`: Recomputes the root from `leaf` at
+///   `index` and the sibling path and compares it with `root`. A path that is too short or
+///   too long for `index` and `leaf_count` never verifies.
+impl MerkleProof {
+    pub fn build(leaves: &[Vec<u8>], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+        let mut path = Vec::new();
+        let mut level: Vec<_> = leaves.iter().map(|leaf| leaf_hash(leaf)).collect();
+        let mut position = index;
+        while level.len() > 1 {
+            if let Some(sibling) = level.get(position ^ 1) {
+                path.push(sibling.clone());
+            }
+            level = next_level(&level);
+            position /= 2;
+        }
+        Some(Self {
+            index,
+            leaf_count: leaves.len(),
+            path,
+        })
+    }
+
+    pub fn verify(&self, leaf: &[u8], root: &[u8]) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+        let mut siblings = self.path.iter();
+        let mut acc = leaf_hash(leaf);
+        let mut position = self.index;
+        let mut len = self.leaf_count;
+        while len > 1 {
+            if position ^ 1 < len {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                acc = if position % 2 == 1 {
+                    node_hash(sibling, &acc)
+                } else {
+                    node_hash(&acc, sibling)
+                };
+            }
+            position /= 2;
+            len = len.div_ceil(2);
+        }
+        siblings.next().is_none() && acc == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx_id(data: &[u8]) -> Vec<u8> {
+        Sha256::digest(data).to_vec()
+    }
+
+    fn leaves(data: &[&str]) -> Vec<Vec<u8>> {
+        data.iter().map(|d| tx_id(d.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_single_leaf_root() {
+        let leaves = leaves(&["tx1"]);
+        assert_eq!(merkle_root(&leaves), leaf_hash(&leaves[0]));
+        assert_ne!(merkle_root(&leaves), leaves[0]);
+    }
+
+    #[test]
+    fn test_empty_root() {
+        assert_eq!(merkle_root(&[]), vec![0u8; 32]);
+    }
+
+    #[test]
+    fn test_root_distinguishes_boundaries() {
+        assert_ne!(
+            merkle_root(&leaves(&["ab", "c"])),
+            merkle_root(&leaves(&["a", "bc"]))
+        );
+    }
+
+    #[test]
+    fn test_root_not_malleable_by_duplicating_last_leaf() {
+        assert_ne!(
+            merkle_root(&leaves(&["a", "b", "c"])),
+            merkle_root(&leaves(&["a", "b", "c", "c"]))
+        );
+    }
+
+    #[test]
+    fn test_proofs_verify_for_every_index() {
+        for count in 1..=9 {
+            let data: Vec<String> = (0..count).map(|i| format!("tx{}", i)).collect();
+            let leaves: Vec<Vec<u8>> = data.iter().map(|d| tx_id(d.as_bytes())).collect();
+            let root = merkle_root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::build(&leaves, index).unwrap();
+                assert!(proof.verify(leaf, &root), "count {} index {}", count, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_rejects_wrong_leaf() {
+        let leaves = leaves(&["tx1", "tx2", "tx3"]);
+        let root = merkle_root(&leaves);
+        let proof = MerkleProof::build(&leaves, 1).unwrap();
+        assert!(!proof.verify(&tx_id(b"forged"), &root));
+        assert!(!proof.verify(&leaves[0], &root));
+    }
+
+    #[test]
+    fn test_proof_binds_index() {
+        let leaves = leaves(&["tx1", "tx2", "tx3", "tx4", "tx5"]);
+        let root = merkle_root(&leaves);
+        for index in 0..leaves.len() {
+            let mut proof = MerkleProof::build(&leaves, index).unwrap();
+            for wrong in (0..leaves.len() + 2).filter(|&wrong| wrong != index) {
+                proof.index = wrong;
+                assert!(
+                    !proof.verify(&leaves[index], &root),
+                    "{} as {}",
+                    index,
+                    wrong
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_rejects_interior_node_as_leaf() {
+        let leaves = leaves(&["tx1", "tx2", "tx3", "tx4"]);
+        let root = merkle_root(&leaves);
+        let node = node_hash(&leaf_hash(&leaves[0]), &leaf_hash(&leaves[1]));
+        let mut proof = MerkleProof::build(&leaves, 0).unwrap();
+        proof.path.remove(0);
+        assert!(!proof.verify(&node, &root));
+        proof.leaf_count = 2;
+        assert!(!proof.verify(&node, &root));
+    }
+
+    #[test]
+    fn test_proof_out_of_range() {
+        assert!(MerkleProof::build(&leaves(&["tx1"]), 1).is_none());
+    }
+}
@@ -0,0 +1,314 @@
+use super::block::Block;
+use super::block_header::BlockHeader;
+use super::target::{U256, meets_target, target_from_compact};
+use super::validation::InvalidBlock;
+use crate::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running search reports its progress by default.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// Workers add their hash counts to the shared total in batches of this size.
+const HASH_BATCH: u64 = 1024;
+
+/// Stops a running search when cancelled. Clones share the same flag, so a token can be
+/// cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The hashes tried so far by a search and the time it has been running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiningStats {
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningStats {
+    /// Returns the average number of hashes per second.
+    pub fn hashrate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.hashes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for MiningStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hashes in {:.1?} ({:.0} H/s)",
+            self.hashes,
+            self.elapsed,
+            self.hashrate()
+        )
+    }
+}
+
+/// The result of `Miner::mine`.
+#[derive(Debug, Clone)]
+pub enum MiningOutcome {
+    /// A copy of the block whose hash is at or below its target.
+    Mined { block: Block, stats: MiningStats },
+    /// The token was cancelled before any worker found a solution.
+    Cancelled { stats: MiningStats },
+}
+
+/// A proof-of-work search that splits the nonce space across worker threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Miner {
+    threads: usize,
+    progress_interval: Duration,
+}
+
+impl Default for Miner {
+    /// Uses one worker per available CPU.
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
+}
+
+/// # Methods
+///
+/// - `new(threads: usize) -> Self`: Creates a miner with `threads` workers, at least one, that
+///   reports progress every `DEFAULT_PROGRESS_INTERVAL`.
+///
+/// - `with_progress_interval(self, interval: Duration) -> Self`: Changes how often `mine`
+///   calls its progress callback.
+///
+/// - `mine(&self, block: &Block, cancel: &CancelToken, progress: impl FnMut(&MiningStats))
+///   -> Result<MiningOutcome, Error>`: Searches for a nonce that puts the hash of `block` at or
+///   below its target, calling `progress` on the calling thread while the search runs. Fails
+///   with `InvalidBlock::InvalidTarget` if the header's `bits` is not a valid compact target.
+///
+/// Worker `i` of `n` tries the nonces `header.nonce + i`, `header.nonce + i + n`, and so on.
+/// When its nonces run past `u64::MAX` it moves the timestamp one second forward and starts
+/// again from nonce `i`, so the workers never hash the same header twice. Every worker stops
+/// as soon as any of them finds a solution or the token is cancelled. Only `nonce` and
+/// `timestamp` change, so the mined header has exactly the format of one mined on a single
+/// thread.
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+
+    pub fn with_progress_interval(self, interval: Duration) -> Self {
+        Self {
+            progress_interval: interval,
+            ..self
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn mine(
+        &self,
+        block: &Block,
+        cancel: &CancelToken,
+        mut progress: impl FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        let target = target_from_compact(block.header.bits).ok_or(InvalidBlock::InvalidTarget)?;
+        let started = Instant::now();
+        let hashes = AtomicU64::new(0);
+        let stop = AtomicBool::new(false);
+        let stats = |hashes: &AtomicU64| MiningStats {
+            hashes: hashes.load(Ordering::Relaxed),
+            elapsed: started.elapsed(),
+        };
+        let solution = thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for worker in 0..self.threads {
+                let sender = sender.clone();
+                let search = Search {
+                    step: self.threads as u64,
+                    worker: worker as u64,
+                    target,
+                    stop: &stop,
+                    cancel,
+                    hashes: &hashes,
+                };
+                let header = block.header.clone();
+                scope.spawn(move || {
+                    if let Some(header) = search.run(header) {
+                        let _ = sender.send(header);
+                    }
+                });
+            }
+            drop(sender);
+            loop {
+                match receiver.recv_timeout(self.progress_interval) {
+                    Ok(header) => {
+                        stop.store(true, Ordering::Relaxed);
+                        break Some(header);
+                    }
+                    Err(RecvTimeoutError::Timeout) => progress(&stats(&hashes)),
+                    Err(RecvTimeoutError::Disconnected) => break None,
+                }
+            }
+        });
+        let stats = stats(&hashes);
+        Ok(match solution {
+            Some(header) => {
+                let mut block = block.clone();
+                block.hash = header.hash();
+                block.header = header;
+                MiningOutcome::Mined { block, stats }
+            }
+            None => MiningOutcome::Cancelled { stats },
+        })
+    }
+}
+
+/// The share of the nonce space searched by one worker.
+struct Search<'a> {
+    step: u64,
+    worker: u64,
+    target: U256,
+    stop: &'a AtomicBool,
+    cancel: &'a CancelToken,
+    hashes: &'a AtomicU64,
+}
+
+impl Search<'_> {
+    fn run(&self, mut header: BlockHeader) -> Option<BlockHeader> {
+        let mut next = header.nonce.checked_add(self.worker);
+        let mut batch = 0;
+        let solution = loop {
+            if self.stop.load(Ordering::Relaxed) || self.cancel.is_cancelled() {
+                break None;
+            }
+            let Some(nonce) = next else {
+                header.timestamp += 1;
+                next = Some(self.worker);
+                continue;
+            };
+            header.nonce = nonce;
+            batch += 1;
+            if meets_target(header.hash().as_bytes(), self.target) {
+                self.stop.store(true, Ordering::Relaxed);
+                break Some(header);
+            }
+            if batch == HASH_BATCH {
+                self.hashes.fetch_add(batch, Ordering::Relaxed);
+                batch = 0;
+            }
+            next = nonce.checked_add(self.step);
+        };
+        self.hashes.fetch_add(batch, Ordering::Relaxed);
+        solution
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::target::{compact_from_difficulty, compact_from_target};
+    use crate::core::transaction::Transaction;
+    use crate::utils::hash::Hash256;
+
+    fn block(bits: u32) -> Block {
+        Block::new(Hash256::ZERO, vec![Transaction::memo("tx1").unwrap()], bits).unwrap()
+    }
+
+    fn mined(outcome: MiningOutcome) -> (Block, MiningStats) {
+        match outcome {
+            MiningOutcome::Mined { block, stats } => (block, stats),
+            MiningOutcome::Cancelled { .. } => panic!("mining was cancelled"),
+        }
+    }
+
+    #[test]
+    fn test_parallel_mine_meets_target() {
+        let original = block(compact_from_difficulty(8));
+        let outcome = Miner::new(4)
+            .mine(&original, &CancelToken::new(), |_| {})
+            .unwrap();
+        let (mined, stats) = mined(outcome);
+
+        assert_eq!(mined.verify(), Ok(()));
+        assert_eq!(mined.check_work(), Ok(()));
+        assert_eq!(mined.header.timestamp, original.header.timestamp);
+        assert_eq!(mined.header.merkle_root, original.header.merkle_root);
+        assert!(stats.hashes >= 1);
+    }
+
+    #[test]
+    fn test_exhausted_nonces_roll_timestamp() {
+        let mut original = block(compact_from_difficulty(4));
+        original.header.nonce = u64::MAX - 1;
+        let outcome = Miner::new(3)
+            .mine(&original, &CancelToken::new(), |_| {})
+            .unwrap();
+        let (mined, _) = mined(outcome);
+
+        assert_eq!(mined.verify(), Ok(()));
+        assert_eq!(mined.check_work(), Ok(()));
+        // Only nonces `u64::MAX - 1` and `u64::MAX` exist at the original timestamp.
+        assert!(
+            mined.header.nonce >= u64::MAX - 1
+                || mined.header.timestamp > original.header.timestamp
+        );
+    }
+
+    #[test]
+    fn test_cancel_from_progress_callback() {
+        // A target of one can practically never be met.
+        let original = block(compact_from_target(U256::ONE));
+        let cancel = CancelToken::new();
+        let miner = Miner::new(2).with_progress_interval(Duration::from_millis(10));
+        let mut reports = Vec::new();
+        let outcome = miner
+            .mine(&original, &cancel, |stats| {
+                reports.push(*stats);
+                cancel.cancel();
+            })
+            .unwrap();
+
+        let MiningOutcome::Cancelled { stats } = outcome else {
+            panic!("an unreachable target was mined");
+        };
+        assert!(!reports.is_empty());
+        assert!(stats.hashes >= reports[0].hashes);
+        assert!(stats.elapsed >= reports[0].elapsed);
+        assert!(stats.hashrate() > 0.0);
+    }
+
+    #[test]
+    fn test_mine_rejects_invalid_target() {
+        let original = block(0x0480_0001);
+        assert!(matches!(
+            Miner::new(1).mine(&original, &CancelToken::new(), |_| {}),
+            Err(Error::InvalidBlock(InvalidBlock::InvalidTarget))
+        ));
+    }
+
+    #[test]
+    fn test_at_least_one_thread() {
+        assert_eq!(Miner::new(0).threads(), 1);
+    }
+}
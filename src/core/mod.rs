@@ -1,4 +1,15 @@
 pub mod block;
 pub mod block_header;
 pub mod blockchain;
-pub mod blockchain_manager;
+pub mod consensus;
+pub mod difficulty;
+pub mod events;
+pub mod mempool;
+pub mod merkle;
+pub mod miner;
+pub mod reward;
+pub mod state;
+pub mod target;
+pub mod transaction;
+pub mod utxo;
+pub mod validation;
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+/// The block reward paid by the coinbase transaction of every block after genesis.
+///
+/// The reward starts at `initial` and halves every `halving_interval` blocks. An interval of
+/// zero keeps the reward constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RewardSchedule {
+    pub initial: u64,
+    pub halving_interval: u64,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        Self {
+            initial: 50,
+            halving_interval: 210,
+        }
+    }
+}
+
+/// # Methods
+///
+/// - `reward_at(&self, height: usize) -> u64`: Returns the reward of the block at `height`.
+///   The genesis block has no coinbase and earns nothing; the reward reaches zero once it has
+///   been halved 64 times.
+impl RewardSchedule {
+    pub fn reward_at(&self, height: usize) -> u64 {
+        if height == 0 {
+            return 0;
+        }
+        let halvings = match self.halving_interval {
+            0 => 0,
+            interval => height as u64 / interval,
+        };
+        u32::try_from(halvings)
+            .ok()
+            .and_then(|halvings| self.initial.checked_shr(halvings))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reward_halves_on_schedule() {
+        let schedule = RewardSchedule {
+            initial: 50,
+            halving_interval: 10,
+        };
+        assert_eq!(schedule.reward_at(0), 0);
+        assert_eq!(schedule.reward_at(1), 50);
+        assert_eq!(schedule.reward_at(9), 50);
+        assert_eq!(schedule.reward_at(10), 25);
+        assert_eq!(schedule.reward_at(25), 12);
+        assert_eq!(schedule.reward_at(10 * 64), 0);
+        assert_eq!(schedule.reward_at(usize::MAX), 0);
+    }
+
+    #[test]
+    fn test_constant_reward_without_interval() {
+        let schedule = RewardSchedule {
+            initial: 7,
+            halving_interval: 0,
+        };
+        assert_eq!(schedule.reward_at(1), 7);
+        assert_eq!(schedule.reward_at(1_000_000), 7);
+    }
+}
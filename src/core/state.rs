@@ -0,0 +1,425 @@
+use super::block::Block;
+use super::transaction::Transaction;
+use crate::utils::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The reason a transaction could not be applied to the world state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The sender's balance is lower than the amount sent.
+    Overdraft {
+        index: usize,
+        balance: u64,
+        amount: u64,
+    },
+    /// The transaction nonce is not the sender's next nonce.
+    InvalidNonce {
+        index: usize,
+        expected: u64,
+        found: u64,
+    },
+    /// An unsigned transaction creates coins outside the genesis block and is not the coinbase.
+    UnauthorizedMint { index: usize },
+    /// Crediting the recipient would overflow its balance.
+    BalanceOverflow { index: usize },
+    /// The transaction has UTXO inputs or outputs on an account chain.
+    UtxoTransaction { index: usize },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Overdraft {
+                index,
+                balance,
+                amount,
+            } => write!(
+                f,
+                "transaction {} spends {} but the sender only has {}",
+                index, amount, balance
+            ),
+            StateError::InvalidNonce {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "transaction {} has nonce {} but {} was expected",
+                index, found, expected
+            ),
+            StateError::UnauthorizedMint { index } => {
+                write!(
+                    f,
+                    "transaction {} creates coins but is not the coinbase",
+                    index
+                )
+            }
+            StateError::BalanceOverflow { index } => {
+                write!(f, "transaction {} overflows the recipient balance", index)
+            }
+            StateError::UtxoTransaction { index } => {
+                write!(
+                    f,
+                    "transaction {} is a UTXO transaction on an account chain",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct WorldState {
+    balances: BTreeMap<String, u64>,
+    nonces: BTreeMap<String, u64>,
+    height: usize,
+    tip: Hash256,
+}
+
+/// Account balances and nonces derived by replaying the chain.
+///
+/// Every signed transaction must carry the sender's next nonce and may not spend more than the
+/// sender's balance, counting its fee. Unsigned transactions with an amount are genesis
+/// allocations or the coinbase, so they are only accepted in the block at height 0 or as the
+/// first transaction of a later block. The coinbase amount is checked by `Blockchain`.
+///
+/// `height` counts the applied blocks and `tip` is the hash of the last one, so a persisted
+/// state can be matched against the chain it was built from.
+///
+/// # Methods
+///
+/// - `from_blocks(blocks: &[Block]) -> Result<Self, (usize, StateError)>`: Replays `blocks`
+///   from genesis, returning the height of the first block that cannot be applied.
+///
+/// - `from_accounts(accounts, height, tip) -> Self`: Restores a state loaded from storage,
+///   given the balance and nonce of every account.
+///
+/// - `apply_block(&mut self, block: &Block) -> Result<(), StateError>`: Applies every
+///   transaction of `block`, leaving the state unchanged if any of them fails.
+///
+/// - `balance_of(&self, address: &str) -> u64` and `nonce_of(&self, address: &str) -> u64`:
+///   Return the balance and next expected nonce of an account, zero if it is unknown.
+impl WorldState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_blocks(blocks: &[Block]) -> Result<Self, (usize, StateError)> {
+        let mut state = Self::new();
+        for (height, block) in blocks.iter().enumerate() {
+            state.apply_block(block).map_err(|err| (height, err))?;
+        }
+        Ok(state)
+    }
+
+    pub fn from_accounts(
+        accounts: impl IntoIterator<Item = (String, (u64, u64))>,
+        height: usize,
+        tip: Hash256,
+    ) -> Self {
+        let mut state = Self {
+            height,
+            tip,
+            ..Self::default()
+        };
+        for (address, (balance, nonce)) in accounts {
+            if nonce > 0 {
+                state.nonces.insert(address.clone(), nonce);
+            }
+            state.balances.insert(address, balance);
+        }
+        state
+    }
+
+    /// Returns every known address with its balance and next nonce, as `from_accounts` takes
+    /// them.
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, (u64, u64))> {
+        self.balances
+            .iter()
+            .map(|(address, &balance)| (address.as_str(), (balance, self.nonce_of(address))))
+    }
+
+    /// Returns the addresses whose balance or nonce `transactions` change.
+    pub fn touched_by(transactions: &[Transaction]) -> BTreeSet<&str> {
+        let mut addresses = BTreeSet::new();
+        for tx in transactions.iter().filter(|tx| !tx.is_memo_only()) {
+            if !tx.sender.is_empty() {
+                addresses.insert(tx.sender.as_str());
+            }
+            addresses.insert(tx.recipient.as_str());
+        }
+        addresses
+    }
+
+    pub fn balance_of(&self, address: &str) -> u64 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn nonce_of(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tip(&self) -> Hash256 {
+        self.tip
+    }
+
+    /// Checks that `transactions` could be applied on top of this state as the next block.
+    pub fn check_transactions(&self, transactions: &[Transaction]) -> Result<(), StateError> {
+        self.clone().apply_transactions(transactions)
+    }
+
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), StateError> {
+        let mut next = self.clone();
+        next.apply_transactions(&block.transactions)?;
+        next.height += 1;
+        next.tip = block.hash;
+        *self = next;
+        Ok(())
+    }
+
+    fn apply_transactions(&mut self, transactions: &[Transaction]) -> Result<(), StateError> {
+        for (index, tx) in transactions.iter().enumerate() {
+            self.apply_transaction(index, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Applies `tx` as transaction `index` of the next block, leaving the state unchanged if
+    /// it fails.
+    pub fn apply_transaction(&mut self, index: usize, tx: &Transaction) -> Result<(), StateError> {
+        if tx.is_memo_only() {
+            return Ok(());
+        }
+        if !tx.inputs.is_empty() || !tx.outputs.is_empty() {
+            return Err(StateError::UtxoTransaction { index });
+        }
+        let mut debited = None;
+        if tx.sender.is_empty() {
+            if self.height != 0 && index != 0 {
+                return Err(StateError::UnauthorizedMint { index });
+            }
+        } else {
+            let expected = self.nonce_of(&tx.sender);
+            if tx.nonce != expected {
+                return Err(StateError::InvalidNonce {
+                    index,
+                    expected,
+                    found: tx.nonce,
+                });
+            }
+            let balance = self.balance_of(&tx.sender);
+            let debit = tx.amount.saturating_add(tx.fee);
+            if balance < debit {
+                return Err(StateError::Overdraft {
+                    index,
+                    balance,
+                    amount: debit,
+                });
+            }
+            debited = Some((balance - debit, expected + 1));
+        }
+        let recipient_balance = match debited {
+            Some((balance, _)) if tx.recipient == tx.sender => balance,
+            _ => self.balance_of(&tx.recipient),
+        };
+        let credited = recipient_balance
+            .checked_add(tx.amount)
+            .ok_or(StateError::BalanceOverflow { index })?;
+        if let Some((balance, nonce)) = debited {
+            self.balances.insert(tx.sender.clone(), balance);
+            self.nonces.insert(tx.sender.clone(), nonce);
+        }
+        self.balances.insert(tx.recipient.clone(), credited);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::target::compact_from_difficulty;
+    use crate::core::utxo::LedgerMode;
+    use crate::utils::crypto::Keypair;
+
+    fn block(transactions: Vec<Transaction>) -> Block {
+        Block::new(Hash256::ZERO, transactions, compact_from_difficulty(1)).unwrap()
+    }
+
+    fn transfer(from: &Keypair, to: &str, amount: u64, nonce: u64) -> Transaction {
+        let mut tx = Transaction::new(&from.address(), to, amount, nonce).unwrap();
+        tx.sign(from);
+        tx
+    }
+
+    #[test]
+    fn test_genesis_allocation_and_transfer() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut state = WorldState::new();
+
+        state
+            .apply_block(&block(vec![
+                Transaction::new("", &alice.address(), 100, 0).unwrap(),
+            ]))
+            .unwrap();
+        state
+            .apply_block(&block(vec![transfer(&alice, &bob, 30, 0)]))
+            .unwrap();
+
+        assert_eq!(state.balance_of(&alice.address()), 70);
+        assert_eq!(state.balance_of(&bob), 30);
+        assert_eq!(state.nonce_of(&alice.address()), 1);
+        assert_eq!(state.nonce_of(&bob), 0);
+        assert_eq!(state.height(), 2);
+    }
+
+    #[test]
+    fn test_overdraft_rejected() {
+        let alice = Keypair::generate();
+        let mut state = WorldState::new();
+        state.apply_block(&block(vec![])).unwrap();
+
+        let result = state.apply_block(&block(vec![transfer(&alice, "bob", 1, 0)]));
+        assert_eq!(
+            result,
+            Err(StateError::Overdraft {
+                index: 0,
+                balance: 0,
+                amount: 1
+            })
+        );
+        assert_eq!(state.height(), 1);
+    }
+
+    #[test]
+    fn test_replayed_nonce_rejected() {
+        let alice = Keypair::generate();
+        let mut state = WorldState::new();
+        state
+            .apply_block(&block(vec![
+                Transaction::new("", &alice.address(), 100, 0).unwrap(),
+            ]))
+            .unwrap();
+        let tx = transfer(&alice, "bob", 10, 0);
+        state.apply_block(&block(vec![tx.clone()])).unwrap();
+
+        let result = state.apply_block(&block(vec![tx]));
+        assert_eq!(
+            result,
+            Err(StateError::InvalidNonce {
+                index: 0,
+                expected: 1,
+                found: 0
+            })
+        );
+        assert_eq!(state.balance_of(&alice.address()), 90);
+    }
+
+    #[test]
+    fn test_failed_block_leaves_state_unchanged() {
+        let alice = Keypair::generate();
+        let mut state = WorldState::new();
+        state
+            .apply_block(&block(vec![
+                Transaction::new("", &alice.address(), 50, 0).unwrap(),
+            ]))
+            .unwrap();
+        let before = state.clone();
+
+        let result = state.apply_block(&block(vec![
+            transfer(&alice, "bob", 40, 0),
+            transfer(&alice, "bob", 40, 1),
+        ]));
+        assert!(result.is_err());
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn test_failed_transaction_leaves_state_unchanged() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let mut state = WorldState::new();
+        state
+            .apply_block(&block(vec![
+                Transaction::new("", &alice.address(), 10, 0).unwrap(),
+                Transaction::new("", &bob, u64::MAX, 0).unwrap(),
+            ]))
+            .unwrap();
+        let before = state.clone();
+
+        let result = state.apply_transaction(0, &transfer(&alice, &bob, 5, 0));
+        assert_eq!(result, Err(StateError::BalanceOverflow { index: 0 }));
+        assert_eq!(state, before);
+        state
+            .apply_transaction(0, &transfer(&alice, &alice.address(), 5, 0))
+            .unwrap();
+        assert_eq!(state.balance_of(&alice.address()), 10);
+        assert_eq!(state.nonce_of(&alice.address()), 1);
+    }
+
+    #[test]
+    fn test_mint_outside_coinbase_rejected() {
+        let mut state = WorldState::new();
+        state.apply_block(&block(vec![])).unwrap();
+        let coinbase = Transaction::coinbase(1, "miner", 50, LedgerMode::Account).unwrap();
+
+        let result = state.apply_block(&block(vec![
+            coinbase.clone(),
+            Transaction::new("", "bob", 5, 0).unwrap(),
+        ]));
+        assert_eq!(result, Err(StateError::UnauthorizedMint { index: 1 }));
+        state.apply_block(&block(vec![coinbase])).unwrap();
+        assert_eq!(state.balance_of("miner"), 50);
+    }
+
+    #[test]
+    fn test_fee_debited_from_sender() {
+        let alice = Keypair::generate();
+        let mut state = WorldState::new();
+        state
+            .apply_block(&block(vec![
+                Transaction::new("", &alice.address(), 10, 0).unwrap(),
+            ]))
+            .unwrap();
+        let mut tx = Transaction::new(&alice.address(), "bob", 9, 0)
+            .unwrap()
+            .with_fee(2);
+        tx.sign(&alice);
+
+        let result = state.check_transactions(std::slice::from_ref(&tx));
+        assert_eq!(
+            result,
+            Err(StateError::Overdraft {
+                index: 0,
+                balance: 10,
+                amount: 11
+            })
+        );
+        let mut tx = Transaction::new(&alice.address(), "bob", 8, 0)
+            .unwrap()
+            .with_fee(2);
+        tx.sign(&alice);
+        state.apply_block(&block(vec![tx])).unwrap();
+        assert_eq!(state.balance_of(&alice.address()), 0);
+        assert_eq!(state.balance_of("bob"), 8);
+    }
+
+    #[test]
+    fn test_from_blocks_reports_height() {
+        let alice = Keypair::generate();
+        let blocks = vec![
+            block(vec![Transaction::memo("genesis").unwrap()]),
+            block(vec![transfer(&alice, "bob", 1, 0)]),
+        ];
+
+        assert_eq!(WorldState::from_blocks(&blocks).unwrap_err().0, 1);
+    }
+}
@@ -0,0 +1,375 @@
+use std::fmt;
+use std::ops::{Add, Div, Not, Shl, Shr};
+
+/// An unsigned 256-bit integer, stored as four 64-bit limbs from most to least significant so
+/// that the derived ordering is numeric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U256([u64; 4]);
+
+/// The easiest target any block may have, equivalent to one leading zero bit.
+pub const POW_LIMIT: U256 = U256([u64::MAX >> 1, u64::MAX, u64::MAX, u64::MAX]);
+
+/// # Methods
+///
+/// - `from_be_slice(bytes: &[u8]) -> Option<Self>`: Reads a 32-byte big-endian number, such as
+///   a block hash.
+///
+/// - `mul_div(self, mul: u64, div: u64) -> Option<Self>`: Returns `self * mul / div` computed
+///   without intermediate overflow, or `None` if the result does not fit in 256 bits.
+///
+/// Shifts by 256 bits or more yield zero, and addition saturates at `U256::MAX`.
+impl U256 {
+    pub const ZERO: Self = Self([0; 4]);
+    pub const ONE: Self = Self([0, 0, 0, 1]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        Self([0, 0, 0, value])
+    }
+
+    pub fn from_be_slice(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 32] = bytes.try_into().ok()?;
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        }
+        Some(Self(limbs))
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.0) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(self) -> bool {
+        self == Self::ZERO
+    }
+
+    pub fn leading_zeros(self) -> u32 {
+        let mut zeros = 0;
+        for limb in self.0 {
+            zeros += limb.leading_zeros();
+            if limb != 0 {
+                break;
+            }
+        }
+        zeros
+    }
+
+    /// Returns the number of significant bits.
+    pub fn bits(self) -> u32 {
+        256 - self.leading_zeros()
+    }
+
+    pub fn low_u64(self) -> u64 {
+        self.0[3]
+    }
+
+    fn bit(self, index: u32) -> bool {
+        (self.0[3 - (index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+
+    fn set_bit(&mut self, index: u32) {
+        self.0[3 - (index / 64) as usize] |= 1 << (index % 64);
+    }
+
+    fn overflowing_add(self, other: Self) -> (Self, bool) {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, first) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, second) = sum.overflowing_add(carry as u64);
+            limbs[i] = sum;
+            carry = first || second;
+        }
+        (Self(limbs), carry)
+    }
+
+    fn wrapping_sub(self, other: Self) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (difference, first) = self.0[i].overflowing_sub(other.0[i]);
+            let (difference, second) = difference.overflowing_sub(borrow as u64);
+            limbs[i] = difference;
+            borrow = first || second;
+        }
+        Self(limbs)
+    }
+
+    pub fn mul_div(self, mul: u64, div: u64) -> Option<Self> {
+        let mut product = [0u64; 5];
+        let mut carry = 0u128;
+        for i in (0..4).rev() {
+            let value = self.0[i] as u128 * mul as u128 + carry;
+            product[i + 1] = value as u64;
+            carry = value >> 64;
+        }
+        product[0] = carry as u64;
+
+        let mut quotient = [0u64; 5];
+        let mut remainder = 0u128;
+        for i in 0..5 {
+            let value = (remainder << 64) | product[i] as u128;
+            quotient[i] = (value / div as u128) as u64;
+            remainder = value % div as u128;
+        }
+        if quotient[0] != 0 {
+            return None;
+        }
+        Some(Self([quotient[1], quotient[2], quotient[3], quotient[4]]))
+    }
+}
+
+impl Add for U256 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        match self.overflowing_add(other) {
+            (_, true) => Self::MAX,
+            (sum, false) => sum,
+        }
+    }
+}
+
+impl Not for U256 {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0.map(|limb| !limb))
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = Self;
+
+    fn shl(self, shift: u32) -> Self {
+        if shift >= 256 {
+            return Self::ZERO;
+        }
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let source = i + limb_shift;
+            if source > 3 {
+                continue;
+            }
+            *limb = self.0[source] << bit_shift;
+            if bit_shift > 0 && source < 3 {
+                *limb |= self.0[source + 1] >> (64 - bit_shift);
+            }
+        }
+        Self(limbs)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = Self;
+
+    fn shr(self, shift: u32) -> Self {
+        if shift >= 256 {
+            return Self::ZERO;
+        }
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate().skip(limb_shift) {
+            let source = i - limb_shift;
+            *limb = self.0[source] >> bit_shift;
+            if bit_shift > 0 && source > 0 {
+                *limb |= self.0[source - 1] << (64 - bit_shift);
+            }
+        }
+        Self(limbs)
+    }
+}
+
+impl Div for U256 {
+    type Output = Self;
+
+    /// Binary long division.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `divisor` is zero.
+    fn div(self, divisor: Self) -> Self {
+        assert!(!divisor.is_zero(), "attempt to divide by zero");
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for index in (0..256).rev() {
+            let carry = remainder.bit(255);
+            remainder = remainder << 1;
+            if self.bit(index) {
+                remainder.0[3] |= 1;
+            }
+            if carry || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.set_bit(index);
+            }
+        }
+        quotient
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for limb in self.0 {
+            write!(f, "{:016x}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a compact target ("nBits"): the top byte is the length of the target in bytes and
+/// the low three bytes are its most significant bytes.
+///
+/// Returns `None` for negative or overflowing encodings, which are never valid targets.
+pub fn target_from_compact(bits: u32) -> Option<U256> {
+    let size = bits >> 24;
+    let mantissa = bits & 0x007f_ffff;
+    if mantissa != 0 && bits & 0x0080_0000 != 0 {
+        return None;
+    }
+    if size <= 3 {
+        return Some(U256::from_u64((mantissa >> (8 * (3 - size))) as u64));
+    }
+    let target = U256::from_u64(mantissa as u64);
+    let shift = 8 * (size - 3);
+    if mantissa != 0 && target.bits() + shift > 256 {
+        return None;
+    }
+    Some(target << shift)
+}
+
+/// Encodes `target` in compact form, keeping its three most significant bytes.
+pub fn compact_from_target(target: U256) -> u32 {
+    let mut size = target.bits().div_ceil(8);
+    let mut mantissa = if size <= 3 {
+        (target.low_u64() << (8 * (3 - size))) as u32
+    } else {
+        (target >> (8 * (size - 3))).low_u64() as u32
+    };
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    (size << 24) | mantissa
+}
+
+/// Returns the target met exactly by hashes with at least `difficulty` leading zero bits.
+pub fn target_from_difficulty(difficulty: u32) -> U256 {
+    U256::MAX >> difficulty
+}
+
+/// Returns the number of leading zero bits every hash meeting `target` has.
+pub fn difficulty_from_target(target: U256) -> u32 {
+    target.leading_zeros()
+}
+
+/// Returns the compact target closest to (and never easier than) `difficulty` leading zero bits.
+pub fn compact_from_difficulty(difficulty: u32) -> u32 {
+    compact_from_target(target_from_difficulty(difficulty))
+}
+
+/// Returns the expected number of hashes needed to find one at or below `target`, that is
+/// `2^256 / (target + 1)`.
+pub fn work_from_target(target: U256) -> U256 {
+    if target == U256::MAX {
+        return U256::ONE;
+    }
+    (!target / (target + U256::ONE)) + U256::ONE
+}
+
+/// Returns `true` if `hash`, read as a 256-bit big-endian number, is at most `target`.
+pub fn meets_target(hash: &[u8], target: U256) -> bool {
+    U256::from_be_slice(hash).is_some_and(|value| value <= target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shifts_and_bits() {
+        let one = U256::ONE;
+        assert_eq!((one << 255).leading_zeros(), 0);
+        assert_eq!((one << 200) >> 200, one);
+        assert_eq!((one << 70).to_be_bytes()[23], 0x40);
+        assert_eq!(U256::MAX >> 256, U256::ZERO);
+        assert_eq!(U256::from_u64(0x1234).bits(), 13);
+        assert_eq!(U256::ZERO.leading_zeros(), 256);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = U256::from_u64(u64::MAX);
+        assert_eq!(a + U256::ONE, U256::ONE << 64);
+        assert_eq!(U256::MAX + U256::ONE, U256::MAX);
+        assert_eq!((U256::ONE << 128) / (U256::ONE << 64), U256::ONE << 64);
+        assert_eq!(U256::MAX / U256::MAX, U256::ONE);
+        assert_eq!(U256::from_u64(100) / U256::from_u64(7), U256::from_u64(14));
+        assert_eq!(
+            U256::MAX.mul_div(3, 4),
+            Some((U256::MAX / U256::from_u64(4)) + (U256::MAX >> 1) + U256::ONE)
+        );
+        assert_eq!(U256::MAX.mul_div(2, 1), None);
+        assert_eq!(
+            U256::from_u64(10).mul_div(u64::MAX, u64::MAX),
+            Some(U256::from_u64(10))
+        );
+    }
+
+    #[test]
+    fn test_compact_roundtrip() {
+        // Bitcoin's genesis target.
+        let target = target_from_compact(0x1d00ffff).unwrap();
+        assert_eq!(
+            target.to_string(),
+            "00000000ffff0000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(compact_from_target(target), 0x1d00ffff);
+        assert_eq!(
+            target_from_compact(0x0300_1234),
+            Some(U256::from_u64(0x1234))
+        );
+        assert_eq!(target_from_compact(0x0112_3456), Some(U256::from_u64(0x12)));
+        assert_eq!(compact_from_target(U256::from_u64(0x80)), 0x0200_8000);
+        assert_eq!(compact_from_target(U256::ZERO), 0);
+        assert_eq!(target_from_compact(0x0480_0001), None);
+        assert_eq!(target_from_compact(0xff12_3456), None);
+    }
+
+    #[test]
+    fn test_difficulty_conversions() {
+        for difficulty in [1, 8, 16, 20, 255] {
+            let compact = compact_from_difficulty(difficulty);
+            let target = target_from_compact(compact).unwrap();
+            assert!(target <= target_from_difficulty(difficulty));
+            assert_eq!(difficulty_from_target(target), difficulty);
+        }
+        assert_eq!(target_from_difficulty(1), POW_LIMIT);
+    }
+
+    #[test]
+    fn test_work() {
+        assert_eq!(work_from_target(U256::MAX), U256::ONE);
+        assert_eq!(work_from_target(U256::MAX >> 1), U256::from_u64(2));
+        assert_eq!(
+            work_from_target(target_from_difficulty(16)),
+            U256::from_u64(65536)
+        );
+    }
+
+    #[test]
+    fn test_meets_target() {
+        let target = target_from_difficulty(8);
+        let mut hash = [0u8; 32];
+        hash[1] = 0xff;
+        assert!(meets_target(&hash, target));
+        hash[0] = 1;
+        assert!(!meets_target(&hash, target));
+        assert!(!meets_target(&[0u8; 31], target));
+    }
+}
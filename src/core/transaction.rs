@@ -0,0 +1,458 @@
+use super::utxo::{LedgerMode, OutPoint, TxInput, TxOutput};
+use crate::error::Error;
+use crate::utils::crypto::{Keypair, address_from_public_key, is_valid_address, verify_signature};
+use crate::utils::hash::bytes_to_hex_string;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub sender: String,
+    pub recipient: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    pub timestamp: u64,
+    pub memo: String,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+}
+
+/// Represents a transaction stored in a block.
+///
+/// A transaction moves `amount` from `sender` to `recipient` and pays `fee` to the miner of
+/// its block. `nonce` orders the transactions of a single sender, `memo` carries an optional
+/// free-form payload and `signature` authorizes the transfer. The sender is the address derived
+/// from `public_key`. A memo-only transaction has an empty sender and recipient, no amount and
+/// no fee, and is not signed.
+///
+/// A coinbase transaction is the unsigned first transaction of a block. It has no sender and
+/// no inputs and pays the block reward plus the fees of the block to the miner.
+///
+/// On a UTXO chain the account fields stay empty; value moves from `inputs` to `outputs` and
+/// each input carries its own public key and signature.
+///
+/// # Methods
+///
+/// - `new(sender: &str, recipient: &str, amount: u64, nonce: u64) -> Result<Self, Error>`:
+///   Creates an unsigned transfer stamped with the current time. It fails with `Error::Clock` if
+///   the system clock is set before the UNIX epoch, as do the other constructors.
+///
+/// - `memo(memo: impl Into<String>) -> Result<Self, Error>`: Creates a data-only transaction
+///   carrying `memo`.
+///
+/// - `utxo(inputs: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Result<Self, Error>`: Creates an
+///   unsigned UTXO transaction spending `inputs`.
+///
+/// - `coinbase(height: usize, miner: &str, value: u64, ledger: LedgerMode) -> Result<Self, Error>`:
+///   Creates the coinbase of the block at `height`, paying `value` to `miner` in the form
+///   `ledger` expects. The height is recorded in the memo so that coinbases paying the same
+///   amount to the same miner have distinct IDs.
+///
+/// - `with_fee(self, fee: u64) -> Self`: Sets the fee; call it before signing.
+///
+/// - `output_value(&self) -> u64`: Returns `amount` plus the value of every output.
+///
+/// - `size(&self) -> usize`: Returns the size of the serialized transaction in bytes, used to
+///   compute fee rates and block sizes.
+///
+/// - `signing_bytes(&self) -> Vec<u8>`: Returns the canonical encoding of every field except
+///   the signatures, in declaration order; inputs contribute only their outpoints. Strings,
+///   byte fields and lists are prefixed with their length as a little-endian `u32` and integers
+///   are little-endian.
+///
+/// - `sign(&mut self, keypair: &Keypair)`: Sets `public_key` and signs `signing_bytes`.
+///
+/// - `sign_input(&mut self, index: usize, keypair: &Keypair)`: Sets the public key of input
+///   `index` and signs `signing_bytes` for it.
+///
+/// - `has_valid_signature(&self) -> bool`: Checks that an unsigned transaction has no sender,
+///   that the sender matches `public_key` and the signature verifies, or that every input
+///   signature verifies. Input ownership is checked against the spent output by `UtxoSet`.
+///
+/// - `id(&self) -> Vec<u8>`: Returns the SHA-256 of `signing_bytes`. The signature is left out
+///   so that the ID is known before signing and cannot be changed by re-encoding a signature.
+impl Transaction {
+    pub fn new(sender: &str, recipient: &str, amount: u64, nonce: u64) -> Result<Self, Error> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Self {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+            fee: 0,
+            nonce,
+            timestamp,
+            memo: String::new(),
+            public_key: Vec::new(),
+            signature: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        })
+    }
+
+    pub fn memo(memo: impl Into<String>) -> Result<Self, Error> {
+        Ok(Self {
+            memo: memo.into(),
+            ..Self::new("", "", 0, 0)?
+        })
+    }
+
+    pub fn utxo(inputs: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Result<Self, Error> {
+        let inputs = inputs
+            .into_iter()
+            .map(|previous_output| TxInput {
+                previous_output,
+                public_key: Vec::new(),
+                signature: Vec::new(),
+            })
+            .collect();
+        Ok(Self {
+            inputs,
+            outputs,
+            ..Self::new("", "", 0, 0)?
+        })
+    }
+
+    pub fn coinbase(
+        height: usize,
+        miner: &str,
+        value: u64,
+        ledger: LedgerMode,
+    ) -> Result<Self, Error> {
+        let tx = match ledger {
+            LedgerMode::Account => Self::new("", miner, value, 0)?,
+            LedgerMode::Utxo => Self::utxo(
+                Vec::new(),
+                vec![TxOutput {
+                    value,
+                    locking_key: miner.to_string(),
+                }],
+            )?,
+        };
+        Ok(Self {
+            memo: format!("coinbase {}", height),
+            ..tx
+        })
+    }
+
+    pub fn with_fee(self, fee: u64) -> Self {
+        Self { fee, ..self }
+    }
+
+    pub fn is_memo_only(&self) -> bool {
+        self.sender.is_empty()
+            && self.recipient.is_empty()
+            && self.amount == 0
+            && self.fee == 0
+            && self.inputs.is_empty()
+            && self.outputs.is_empty()
+    }
+
+    /// Returns `true` if the transaction creates coins: it has no sender and no inputs but is
+    /// not memo-only. Only genesis allocations and the first transaction of a block may do so.
+    pub fn is_coinbase(&self) -> bool {
+        self.sender.is_empty() && self.inputs.is_empty() && !self.is_memo_only()
+    }
+
+    pub fn output_value(&self) -> u64 {
+        self.outputs
+            .iter()
+            .fold(self.amount, |sum, output| sum.saturating_add(output.value))
+    }
+
+    /// Returns the addresses that receive value from this transaction.
+    pub fn recipients(&self) -> Vec<&str> {
+        if !self.outputs.is_empty() {
+            self.outputs
+                .iter()
+                .map(|output| output.locking_key.as_str())
+                .collect()
+        } else if self.is_memo_only() || !self.inputs.is_empty() {
+            Vec::new()
+        } else {
+            vec![self.recipient.as_str()]
+        }
+    }
+
+    /// Whether every address this transaction pays is well-formed.
+    pub fn has_valid_recipients(&self) -> bool {
+        self.recipients().into_iter().all(is_valid_address)
+    }
+
+    pub fn size(&self) -> usize {
+        bincode::serialized_size(self).map_or(usize::MAX, |size| size as usize)
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_str(&mut bytes, &self.sender);
+        write_str(&mut bytes, &self.recipient);
+        bytes.extend_from_slice(&self.amount.to_le_bytes());
+        bytes.extend_from_slice(&self.fee.to_le_bytes());
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        write_str(&mut bytes, &self.memo);
+        write_bytes(&mut bytes, &self.public_key);
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        for input in &self.inputs {
+            write_bytes(&mut bytes, &input.previous_output.txid);
+            bytes.extend_from_slice(&input.previous_output.index.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.outputs.len() as u32).to_le_bytes());
+        for output in &self.outputs {
+            bytes.extend_from_slice(&output.value.to_le_bytes());
+            write_str(&mut bytes, &output.locking_key);
+        }
+        bytes
+    }
+
+    pub fn sign(&mut self, keypair: &Keypair) {
+        self.public_key = keypair.public_key();
+        self.signature = keypair.sign(&self.signing_bytes());
+    }
+
+    pub fn sign_input(&mut self, index: usize, keypair: &Keypair) {
+        let signature = keypair.sign(&self.signing_bytes());
+        let input = &mut self.inputs[index];
+        input.public_key = keypair.public_key();
+        input.signature = signature;
+    }
+
+    pub fn has_valid_signature(&self) -> bool {
+        if !self.inputs.is_empty() {
+            let message = self.signing_bytes();
+            return self.sender.is_empty()
+                && self.signature.is_empty()
+                && self.public_key.is_empty()
+                && self
+                    .inputs
+                    .iter()
+                    .all(|input| verify_signature(&input.public_key, &message, &input.signature));
+        }
+        if self.sender.is_empty() {
+            return self.signature.is_empty() && self.public_key.is_empty();
+        }
+        address_from_public_key(&self.public_key) == self.sender
+            && verify_signature(&self.public_key, &self.signing_bytes(), &self.signature)
+    }
+
+    pub fn id(&self) -> Vec<u8> {
+        Sha256::digest(self.signing_bytes()).to_vec()
+    }
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_bytes(bytes, value.as_bytes());
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = bytes_to_hex_string(&self.id());
+        if self.is_memo_only() {
+            return write!(f, "[{}] memo: {}", &id[..16], self.memo);
+        }
+        if !self.inputs.is_empty() || !self.outputs.is_empty() {
+            write!(
+                f,
+                "[{}] {} inputs -> {} outputs: {}",
+                &id[..16],
+                self.inputs.len(),
+                self.outputs.len(),
+                self.output_value()
+            )?;
+            if !self.memo.is_empty() {
+                write!(f, " memo: {}", self.memo)?;
+            }
+            return Ok(());
+        }
+        write!(
+            f,
+            "[{}] {} -> {}: {} (nonce {})",
+            &id[..16],
+            self.sender,
+            self.recipient,
+            self.amount,
+            self.nonce
+        )?;
+        if self.fee > 0 {
+            write!(f, " fee: {}", self.fee)?;
+        }
+        if !self.memo.is_empty() {
+            write!(f, " memo: {}", self.memo)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_creation() {
+        let tx = Transaction::new("alice", "bob", 10, 3).unwrap();
+
+        assert_eq!(tx.sender, "alice");
+        assert_eq!(tx.recipient, "bob");
+        assert_eq!(tx.amount, 10);
+        assert_eq!(tx.nonce, 3);
+        assert!(tx.timestamp > 0);
+        assert!(tx.signature.is_empty());
+        assert!(!tx.is_memo_only());
+    }
+
+    #[test]
+    fn test_memo_transaction() {
+        let tx = Transaction::memo("hello").unwrap();
+
+        assert_eq!(tx.memo, "hello");
+        assert!(tx.is_memo_only());
+    }
+
+    #[test]
+    fn test_id_is_stable_and_ignores_signature() {
+        let tx = Transaction::new("alice", "bob", 10, 0).unwrap();
+        let mut signed = tx.clone();
+        signed.signature = vec![1, 2, 3];
+
+        assert_eq!(tx.id().len(), 32);
+        assert_eq!(tx.id(), tx.clone().id());
+        assert_eq!(tx.id(), signed.id());
+    }
+
+    #[test]
+    fn test_id_distinguishes_field_boundaries() {
+        let a = Transaction::new("ab", "c", 1, 0).unwrap();
+        let mut b = Transaction::new("a", "bc", 1, 0).unwrap();
+        b.timestamp = a.timestamp;
+
+        assert_ne!(a.id(), b.id());
+    }
+
+    #[test]
+    fn test_id_vector() {
+        let tx = Transaction {
+            sender: "alice".to_string(),
+            recipient: "bob".to_string(),
+            amount: 10,
+            fee: 2,
+            nonce: 1,
+            timestamp: 1_700_000_000,
+            memo: "hi".to_string(),
+            public_key: vec![0xAB],
+            signature: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        assert_eq!(
+            bytes_to_hex_string(&tx.signing_bytes()),
+            "05000000616c69636503000000626f620a000000000000000200000000000000010000000000000000f153650000\
+             000002000000686901000000ab0000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_signed_transaction_verifies() {
+        let keypair = Keypair::generate();
+        let mut tx = Transaction::new(&keypair.address(), "bob", 10, 0).unwrap();
+        tx.sign(&keypair);
+
+        assert!(tx.has_valid_signature());
+    }
+
+    #[test]
+    fn test_tampered_transaction_fails_verification() {
+        let keypair = Keypair::generate();
+        let mut tx = Transaction::new(&keypair.address(), "bob", 10, 0).unwrap();
+        tx.sign(&keypair);
+        tx.amount = 1000;
+
+        assert!(!tx.has_valid_signature());
+    }
+
+    #[test]
+    fn test_signature_from_other_key_fails_verification() {
+        let owner = Keypair::generate();
+        let thief = Keypair::generate();
+        let mut tx = Transaction::new(&owner.address(), "bob", 10, 0).unwrap();
+        tx.sign(&thief);
+
+        assert!(!tx.has_valid_signature());
+    }
+
+    #[test]
+    fn test_utxo_inputs_signatures() {
+        let keypair = Keypair::generate();
+        let outputs = vec![TxOutput {
+            value: 5,
+            locking_key: "bob".to_string(),
+        }];
+        let mut tx = Transaction::utxo(
+            vec![OutPoint::new(vec![1; 32], 0), OutPoint::new(vec![2; 32], 1)],
+            outputs,
+        )
+        .unwrap();
+        assert!(!tx.is_memo_only());
+        assert!(!tx.has_valid_signature());
+
+        tx.sign_input(0, &keypair);
+        tx.sign_input(1, &keypair);
+        assert!(tx.has_valid_signature());
+        assert_eq!(tx.recipients(), vec!["bob"]);
+
+        tx.outputs[0].value = 6;
+        assert!(!tx.has_valid_signature());
+
+        // An unvalidated transaction can claim outputs that overflow their sum.
+        tx.outputs.push(TxOutput {
+            value: u64::MAX,
+            locking_key: "carol".to_string(),
+        });
+        assert!(tx.to_string().ends_with(&u64::MAX.to_string()));
+    }
+
+    #[test]
+    fn test_coinbase_transactions() {
+        let account = Transaction::coinbase(3, "miner", 50, LedgerMode::Account).unwrap();
+        assert!(account.is_coinbase());
+        assert_eq!(account.recipients(), vec!["miner"]);
+        assert_eq!(account.output_value(), 50);
+        assert!(account.has_valid_signature());
+
+        let utxo = Transaction::coinbase(3, "miner", 50, LedgerMode::Utxo).unwrap();
+        assert!(utxo.is_coinbase());
+        assert_eq!(utxo.recipients(), vec!["miner"]);
+        assert_eq!(utxo.output_value(), 50);
+
+        let mut next = Transaction::coinbase(4, "miner", 50, LedgerMode::Utxo).unwrap();
+        next.timestamp = utxo.timestamp;
+        assert_ne!(utxo.id(), next.id());
+        assert!(!Transaction::memo("hello").unwrap().is_coinbase());
+        assert!(
+            !Transaction::memo("hello")
+                .unwrap()
+                .with_fee(1)
+                .is_memo_only()
+        );
+    }
+
+    #[test]
+    fn test_unsigned_transactions() {
+        assert!(Transaction::memo("hello").unwrap().has_valid_signature());
+        assert!(
+            !Transaction::new("alice", "bob", 10, 0)
+                .unwrap()
+                .has_valid_signature()
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use super::block::Block;
+use super::transaction::Transaction;
+use crate::utils::crypto::address_from_public_key;
+use crate::utils::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Reference to output `index` of the transaction with ID `txid`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutPoint {
+    pub txid: Vec<u8>,
+    pub index: u32,
+}
+
+/// Spends a previous output. `public_key` must hash to the output's locking address and
+/// `signature` must sign the spending transaction's `signing_bytes`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TxInput {
+    pub previous_output: OutPoint,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Creates a spendable output of `value` locked to the address `locking_key`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TxOutput {
+    pub value: u64,
+    pub locking_key: String,
+}
+
+/// Selects how transactions move value on a chain.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerMode {
+    /// Transfers debit `sender` and credit `recipient`, tracked by `WorldState`.
+    #[default]
+    Account,
+    /// Transactions consume previous outputs and create new ones, tracked by `UtxoSet`.
+    Utxo,
+}
+
+/// The reason a transaction could not be applied to the UTXO set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoError {
+    /// The input refers to an output that does not exist or was spent in an earlier block.
+    MissingOutput { index: usize, input: usize },
+    /// The input spends an output already spent earlier in the same block.
+    DoubleSpend { index: usize, input: usize },
+    /// The input's public key does not match the output's locking key.
+    InvalidUnlock { index: usize, input: usize },
+    /// The outputs and the fee are worth more than the inputs.
+    ValueExceedsInputs { index: usize },
+    /// A transaction without inputs creates coins outside the genesis block and is not the
+    /// coinbase.
+    UnauthorizedMint { index: usize },
+    /// The transaction uses the account fields `sender`, `recipient` or `amount`.
+    AccountTransaction { index: usize },
+}
+
+impl fmt::Display for UtxoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtxoError::MissingOutput { index, input } => write!(
+                f,
+                "transaction {} input {} spends an unknown or spent output",
+                index, input
+            ),
+            UtxoError::DoubleSpend { index, input } => write!(
+                f,
+                "transaction {} input {} double spends an output within the block",
+                index, input
+            ),
+            UtxoError::InvalidUnlock { index, input } => write!(
+                f,
+                "transaction {} input {} does not match the output's locking key",
+                index, input
+            ),
+            UtxoError::ValueExceedsInputs { index } => {
+                write!(f, "transaction {} creates more value than it spends", index)
+            }
+            UtxoError::UnauthorizedMint { index } => {
+                write!(
+                    f,
+                    "transaction {} creates coins but is not the coinbase",
+                    index
+                )
+            }
+            UtxoError::AccountTransaction { index } => {
+                write!(
+                    f,
+                    "transaction {} is an account transfer on a UTXO chain",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for UtxoError {}
+
+impl OutPoint {
+    pub fn new(txid: Vec<u8>, index: u32) -> Self {
+        Self { txid, index }
+    }
+
+    /// Encodes the outpoint as `txid || index` (big-endian) for use as a sorted storage key.
+    pub fn to_key(&self) -> Vec<u8> {
+        let mut key = self.txid.clone();
+        key.extend_from_slice(&self.index.to_be_bytes());
+        key
+    }
+
+    pub fn from_key(key: &[u8]) -> Option<Self> {
+        let split = key.len().checked_sub(4)?;
+        let index = u32::from_be_bytes(key[split..].try_into().ok()?);
+        Some(Self::new(key[..split].to_vec(), index))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct UtxoSet {
+    outputs: BTreeMap<OutPoint, TxOutput>,
+    height: usize,
+    tip: Hash256,
+}
+
+/// The set of unspent transaction outputs derived by replaying the chain.
+///
+/// Like `WorldState`, `height` counts the applied blocks and `tip` is the hash of the last one.
+/// Transactions without inputs are only accepted in the genesis block or as the coinbase, the
+/// first transaction of a later block. The outputs and fee of any other transaction may not be
+/// worth more than its inputs; any remainder is burned.
+///
+/// # Methods
+///
+/// - `from_blocks(blocks: &[Block]) -> Result<Self, (usize, UtxoError)>`: Replays `blocks` from
+///   genesis, returning the height of the first block that cannot be applied.
+///
+/// - `from_outputs(outputs, height, tip) -> Self`: Restores a set loaded from storage.
+///
+/// - `apply_block(&mut self, block: &Block) -> Result<(), UtxoError>`: Spends the inputs and
+///   adds the outputs of every transaction of `block`, leaving the set unchanged on error.
+///
+/// - `balance_of(&self, address: &str) -> u64`: Sums the unspent outputs locked to `address`.
+impl UtxoSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_blocks(blocks: &[Block]) -> Result<Self, (usize, UtxoError)> {
+        let mut set = Self::new();
+        for (height, block) in blocks.iter().enumerate() {
+            set.apply_block(block).map_err(|err| (height, err))?;
+        }
+        Ok(set)
+    }
+
+    pub fn from_outputs(
+        outputs: impl IntoIterator<Item = (OutPoint, TxOutput)>,
+        height: usize,
+        tip: Hash256,
+    ) -> Self {
+        Self {
+            outputs: outputs.into_iter().collect(),
+            height,
+            tip,
+        }
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.outputs.get(outpoint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &TxOutput)> {
+        self.outputs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tip(&self) -> Hash256 {
+        self.tip
+    }
+
+    pub fn balance_of(&self, address: &str) -> u64 {
+        self.unspent_for(address)
+            .map(|(_, output)| output.value)
+            .sum()
+    }
+
+    pub fn unspent_for<'a>(
+        &'a self,
+        address: &'a str,
+    ) -> impl Iterator<Item = (&'a OutPoint, &'a TxOutput)> {
+        self.outputs
+            .iter()
+            .filter(move |(_, output)| output.locking_key == address)
+    }
+
+    /// Checks that `transactions` could be applied on top of this set as the next block.
+    pub fn check_transactions(&self, transactions: &[Transaction]) -> Result<(), UtxoError> {
+        self.clone().apply_transactions(transactions)
+    }
+
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), UtxoError> {
+        let mut next = self.clone();
+        next.apply_transactions(&block.transactions)?;
+        next.height += 1;
+        next.tip = block.hash;
+        *self = next;
+        Ok(())
+    }
+
+    fn apply_transactions(&mut self, transactions: &[Transaction]) -> Result<(), UtxoError> {
+        let mut spent = BTreeSet::new();
+        for (index, tx) in transactions.iter().enumerate() {
+            self.apply_transaction(index, tx, &mut spent)?;
+        }
+        Ok(())
+    }
+
+    /// Applies `tx` as transaction `index` of the next block, leaving the set unchanged if it
+    /// fails. `spent` holds the outputs spent by the earlier transactions of the block, which
+    /// tells a double spend from an unknown output, and receives the outputs `tx` spends.
+    pub fn apply_transaction(
+        &mut self,
+        index: usize,
+        tx: &Transaction,
+        spent: &mut BTreeSet<OutPoint>,
+    ) -> Result<(), UtxoError> {
+        if tx.is_memo_only() {
+            return Ok(());
+        }
+        if !tx.sender.is_empty() || !tx.recipient.is_empty() || tx.amount > 0 {
+            return Err(UtxoError::AccountTransaction { index });
+        }
+        if tx.inputs.is_empty() && self.height != 0 && index != 0 {
+            return Err(UtxoError::UnauthorizedMint { index });
+        }
+        let mut input_value: u64 = 0;
+        for (input_index, input) in tx.inputs.iter().enumerate() {
+            let outpoint = &input.previous_output;
+            let repeated = tx.inputs[..input_index]
+                .iter()
+                .any(|earlier| earlier.previous_output == *outpoint);
+            let Some(output) = self.outputs.get(outpoint).filter(|_| !repeated) else {
+                return Err(if repeated || spent.contains(outpoint) {
+                    UtxoError::DoubleSpend {
+                        index,
+                        input: input_index,
+                    }
+                } else {
+                    UtxoError::MissingOutput {
+                        index,
+                        input: input_index,
+                    }
+                });
+            };
+            if address_from_public_key(&input.public_key) != output.locking_key {
+                return Err(UtxoError::InvalidUnlock {
+                    index,
+                    input: input_index,
+                });
+            }
+            input_value = input_value.saturating_add(output.value);
+        }
+        let output_value = tx
+            .outputs
+            .iter()
+            .try_fold(tx.fee, |sum, output| sum.checked_add(output.value));
+        if !tx.inputs.is_empty() && output_value.is_none_or(|value| value > input_value) {
+            return Err(UtxoError::ValueExceedsInputs { index });
+        }
+        for input in &tx.inputs {
+            self.outputs.remove(&input.previous_output);
+            spent.insert(input.previous_output.clone());
+        }
+        let txid = tx.id();
+        for (output_index, output) in tx.outputs.iter().enumerate() {
+            self.outputs.insert(
+                OutPoint::new(txid.clone(), output_index as u32),
+                output.clone(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::target::compact_from_difficulty;
+    use crate::utils::crypto::Keypair;
+
+    fn block(transactions: Vec<Transaction>) -> Block {
+        Block::new(Hash256::ZERO, transactions, compact_from_difficulty(1)).unwrap()
+    }
+
+    fn output(value: u64, owner: &str) -> TxOutput {
+        TxOutput {
+            value,
+            locking_key: owner.to_string(),
+        }
+    }
+
+    fn spend(owner: &Keypair, outpoints: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Transaction {
+        let mut tx = Transaction::utxo(outpoints, outputs).unwrap();
+        for input in 0..tx.inputs.len() {
+            tx.sign_input(input, owner);
+        }
+        tx
+    }
+
+    fn funded(owner: &Keypair, value: u64) -> (UtxoSet, OutPoint) {
+        let genesis = Transaction::utxo(vec![], vec![output(value, &owner.address())]).unwrap();
+        let outpoint = OutPoint::new(genesis.id(), 0);
+        let mut set = UtxoSet::new();
+        set.apply_block(&block(vec![genesis])).unwrap();
+        (set, outpoint)
+    }
+
+    #[test]
+    fn test_spend_output() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let (mut set, coin) = funded(&alice, 50);
+        let tx = spend(
+            &alice,
+            vec![coin.clone()],
+            vec![output(30, &bob), output(15, &alice.address())],
+        );
+
+        set.apply_block(&block(vec![tx])).unwrap();
+
+        assert!(set.get(&coin).is_none());
+        assert_eq!(set.balance_of(&bob), 30);
+        assert_eq!(set.balance_of(&alice.address()), 15);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_double_spend_within_block() {
+        let alice = Keypair::generate();
+        let (mut set, coin) = funded(&alice, 50);
+        let first = spend(&alice, vec![coin.clone()], vec![output(50, "bob")]);
+        let second = spend(&alice, vec![coin], vec![output(50, "carol")]);
+
+        let result = set.apply_block(&block(vec![first, second]));
+        assert_eq!(result, Err(UtxoError::DoubleSpend { index: 1, input: 0 }));
+        assert_eq!(set.balance_of(&alice.address()), 50);
+    }
+
+    #[test]
+    fn test_double_spend_across_blocks() {
+        let alice = Keypair::generate();
+        let (mut set, coin) = funded(&alice, 50);
+        set.apply_block(&block(vec![spend(
+            &alice,
+            vec![coin.clone()],
+            vec![output(50, "bob")],
+        )]))
+        .unwrap();
+
+        let result = set.apply_block(&block(vec![spend(
+            &alice,
+            vec![coin],
+            vec![output(50, "carol")],
+        )]));
+        assert_eq!(result, Err(UtxoError::MissingOutput { index: 0, input: 0 }));
+    }
+
+    #[test]
+    fn test_spend_requires_owner_key() {
+        let alice = Keypair::generate();
+        let mallory = Keypair::generate();
+        let (mut set, coin) = funded(&alice, 50);
+
+        let result = set.apply_block(&block(vec![spend(
+            &mallory,
+            vec![coin],
+            vec![output(50, &mallory.address())],
+        )]));
+        assert_eq!(result, Err(UtxoError::InvalidUnlock { index: 0, input: 0 }));
+    }
+
+    #[test]
+    fn test_failed_transaction_leaves_set_unchanged() {
+        let alice = Keypair::generate();
+        let (mut set, coin) = funded(&alice, 50);
+        let mut tx = Transaction::utxo(vec![coin.clone(), coin], vec![output(50, "bob")]).unwrap();
+        tx.sign_input(0, &alice);
+        tx.sign_input(1, &alice);
+        let before = set.clone();
+        let mut spent = BTreeSet::new();
+
+        let result = set.apply_transaction(0, &tx, &mut spent);
+        assert_eq!(result, Err(UtxoError::DoubleSpend { index: 0, input: 1 }));
+        assert_eq!(set, before);
+        assert!(spent.is_empty());
+    }
+
+    #[test]
+    fn test_outputs_cannot_exceed_inputs() {
+        let alice = Keypair::generate();
+        let (mut set, coin) = funded(&alice, 50);
+
+        let result = set.apply_block(&block(vec![spend(
+            &alice,
+            vec![coin.clone()],
+            vec![output(51, "bob")],
+        )]));
+        assert_eq!(result, Err(UtxoError::ValueExceedsInputs { index: 0 }));
+
+        let mut tx = Transaction::utxo(vec![coin], vec![output(49, "bob")])
+            .unwrap()
+            .with_fee(2);
+        tx.sign_input(0, &alice);
+        assert_eq!(
+            set.check_transactions(&[tx]),
+            Err(UtxoError::ValueExceedsInputs { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_mint_and_account_transactions_rejected() {
+        let (set, _) = funded(&Keypair::generate(), 1);
+
+        let coinbase = Transaction::coinbase(1, "miner", 50, LedgerMode::Utxo).unwrap();
+        let mint = Transaction::utxo(vec![], vec![output(5, "bob")]).unwrap();
+        assert_eq!(
+            set.check_transactions(&[coinbase.clone(), mint]),
+            Err(UtxoError::UnauthorizedMint { index: 1 })
+        );
+        assert_eq!(set.check_transactions(&[coinbase]), Ok(()));
+        let transfer = Transaction::new("alice", "bob", 5, 0).unwrap();
+        assert_eq!(
+            set.check_transactions(&[transfer]),
+            Err(UtxoError::AccountTransaction { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_outpoint_key_roundtrip() {
+        let outpoint = OutPoint::new(vec![7u8; 32], 513);
+        assert_eq!(OutPoint::from_key(&outpoint.to_key()), Some(outpoint));
+        assert_eq!(OutPoint::from_key(&[1, 2]), None);
+    }
+}
@@ -0,0 +1,137 @@
+use super::state::StateError;
+use super::utxo::UtxoError;
+use std::fmt;
+
+/// The reason a block was rejected during validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidBlock {
+    /// `header.version` is not a header encoding this node understands.
+    UnsupportedVersion(u32),
+    /// The stored hash does not match the hash recomputed from the block contents.
+    HashMismatch,
+    /// `header.merkle_root` does not match the block's transactions.
+    MerkleRootMismatch,
+    /// The transaction at `index` has a missing or invalid signature.
+    InvalidSignature { index: usize },
+    /// The transaction at `index` pays a malformed address.
+    InvalidRecipient { index: usize },
+    /// The hash is greater than the target encoded in `header.bits`.
+    InsufficientWork,
+    /// `header.bits` is not a valid compact target.
+    InvalidTarget,
+    /// `header.prev_hash` does not match the hash of the previous block.
+    BrokenLink,
+    /// The block's timestamp is earlier than its predecessor's.
+    TimestampNotMonotonic,
+    /// The block's timestamp is more than `MAX_FUTURE_DRIFT` seconds past the local clock.
+    TimestampTooFarAhead,
+    /// `header.bits` differs from the compact target required by the chain.
+    DifficultyMismatch { expected: u32, found: u32 },
+    /// The first transaction of a block after genesis is not a coinbase.
+    MissingCoinbase,
+    /// The coinbase pays more than the block reward plus the fees of the block.
+    CoinbaseTooLarge { allowed: u64, found: u64 },
+    /// The block's transactions cannot be applied to the ledger state.
+    Ledger(StateError),
+    /// The block's transactions cannot be applied to the UTXO set.
+    Utxo(UtxoError),
+    /// The chain has no genesis block.
+    MissingGenesis,
+    /// `header.prev_hash` is not the hash of any known block.
+    UnknownParent,
+    /// The seal is missing, malformed or not valid for the chain's consensus engine.
+    InvalidSeal,
+    /// The block is signed by `found` but the consensus engine scheduled `expected`.
+    WrongSigner { expected: String, found: String },
+}
+
+impl fmt::Display for InvalidBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidBlock::UnsupportedVersion(version) => {
+                write!(f, "unsupported header version {}", version)
+            }
+            InvalidBlock::HashMismatch => write!(f, "stored hash does not match block contents"),
+            InvalidBlock::MerkleRootMismatch => {
+                write!(f, "merkle root does not match the transactions")
+            }
+            InvalidBlock::InvalidSignature { index } => {
+                write!(f, "transaction {} has an invalid signature", index)
+            }
+            InvalidBlock::InvalidRecipient { index } => {
+                write!(f, "transaction {} pays an invalid address", index)
+            }
+            InvalidBlock::InsufficientWork => {
+                write!(f, "hash is above the required target")
+            }
+            InvalidBlock::InvalidTarget => write!(f, "target bits are not a valid compact target"),
+            InvalidBlock::BrokenLink => {
+                write!(f, "previous hash does not link to the previous block")
+            }
+            InvalidBlock::TimestampNotMonotonic => {
+                write!(f, "timestamp is earlier than the previous block")
+            }
+            InvalidBlock::TimestampTooFarAhead => {
+                write!(f, "timestamp is too far ahead of the local clock")
+            }
+            InvalidBlock::DifficultyMismatch { expected, found } => {
+                write!(
+                    f,
+                    "target bits {:#010x} do not match expected {:#010x}",
+                    found, expected
+                )
+            }
+            InvalidBlock::MissingCoinbase => {
+                write!(f, "first transaction is not a coinbase")
+            }
+            InvalidBlock::CoinbaseTooLarge { allowed, found } => write!(
+                f,
+                "coinbase pays {} but reward and fees only allow {}",
+                found, allowed
+            ),
+            InvalidBlock::Ledger(err) => write!(f, "ledger rule violated: {}", err),
+            InvalidBlock::Utxo(err) => write!(f, "UTXO rule violated: {}", err),
+            InvalidBlock::MissingGenesis => write!(f, "chain has no genesis block"),
+            InvalidBlock::UnknownParent => write!(f, "previous hash is not a known block"),
+            InvalidBlock::InvalidSeal => write!(f, "block seal is missing or invalid"),
+            InvalidBlock::WrongSigner { expected, found } => write!(
+                f,
+                "block is sealed by {} but {} was scheduled",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidBlock {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InvalidBlock::Ledger(err) => Some(err),
+            InvalidBlock::Utxo(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by `Blockchain::validate`, naming the first invalid height.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub height: usize,
+    pub reason: InvalidBlock,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid block at height {}: {}",
+            self.height, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.reason)
+    }
+}
@@ -0,0 +1,194 @@
+use crate::core::consensus::SealError;
+use crate::core::mempool::MempoolError;
+use crate::core::validation::{InvalidBlock, ValidationError};
+use crate::storage::archive::ArchiveError;
+use crate::utils::hash::HexError;
+use std::fmt;
+use std::time::SystemTimeError;
+
+/// Result type of the fallible public functions of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error returned by the public API of `core`, `storage` and `utils`.
+///
+/// Checks that judge a single component, such as `Block::verify`,
+/// `ConsensusEngine::verify_seal` or `WorldState::apply_block`, return their specific reason
+/// instead, so that it can be embedded in `InvalidBlock` and `ValidationError`. Every reason
+/// converts into `Error` with `?`, and `source` returns the wrapped error.
+#[derive(Debug)]
+pub enum Error {
+    /// A block or the transactions of a new block were rejected.
+    InvalidBlock(InvalidBlock),
+    /// A chain failed validation.
+    Validation(ValidationError),
+    /// A transaction was not accepted into the mempool.
+    Mempool(MempoolError),
+    /// The consensus engine does not let this node seal the next block.
+    Consensus(SealError),
+    /// The underlying sled database failed.
+    Storage(sled::Error),
+    /// A file store could not be read or written.
+    Io(std::io::Error),
+    /// A value could not be serialized or deserialized.
+    Serialization(bincode::Error),
+    /// The database holds no blockchain and `create_if_missing` was not set.
+    MissingChain,
+    /// A stored block or the stored chain could not be deserialized. The bytes were moved to
+    /// `quarantine_key` so that the next save cannot overwrite them.
+    Undecodable {
+        quarantine_key: String,
+        source: bincode::Error,
+    },
+    /// The height index of the database refers to a block that is not stored.
+    MissingBlock { height: usize },
+    /// A chain of the first release breaks the rules it was mined with, or cannot be
+    /// converted to a valid chain. Its single key is left in place.
+    LegacyChain(ValidationError),
+    /// The database was written with a newer schema than this version can read.
+    UnsupportedSchema { found: u32, supported: u32 },
+    /// A chain archive could not be written or read.
+    Archive(ArchiveError),
+    /// A string is not valid hexadecimal.
+    Hex(HexError),
+    /// The system clock is set before the UNIX epoch.
+    Clock(SystemTimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidBlock(err) => write!(f, "invalid block: {}", err),
+            Error::Validation(err) => write!(f, "chain validation failed: {}", err),
+            Error::Mempool(err) => write!(f, "mempool refused the transaction: {}", err),
+            Error::Consensus(err) => write!(f, "cannot seal the block: {}", err),
+            Error::Storage(err) => write!(f, "storage error: {}", err),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Serialization(err) => write!(f, "serialization error: {}", err),
+            Error::MissingChain => write!(f, "no blockchain found in the database"),
+            Error::Undecodable { quarantine_key, .. } => write!(
+                f,
+                "stored data could not be decoded; raw bytes moved to '{}'",
+                quarantine_key
+            ),
+            Error::MissingBlock { height } => {
+                write!(f, "block at height {} is missing from the database", height)
+            }
+            Error::LegacyChain(err) => write!(f, "stored legacy chain cannot be migrated: {}", err),
+            Error::UnsupportedSchema { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the supported version {}",
+                found, supported
+            ),
+            Error::Archive(err) => write!(f, "archive error: {}", err),
+            Error::Hex(err) => write!(f, "invalid hex string: {}", err),
+            Error::Clock(err) => write!(f, "system clock error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidBlock(err) => Some(err),
+            Error::Validation(err) => Some(err),
+            Error::Mempool(err) => Some(err),
+            Error::Consensus(err) => Some(err),
+            Error::Storage(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Serialization(err) => Some(err),
+            Error::MissingChain => None,
+            Error::Undecodable { source, .. } => Some(source),
+            Error::MissingBlock { .. } => None,
+            Error::LegacyChain(err) => Some(err),
+            Error::UnsupportedSchema { .. } => None,
+            Error::Archive(err) => Some(err),
+            Error::Hex(err) => Some(err),
+            Error::Clock(err) => Some(err),
+        }
+    }
+}
+
+impl From<InvalidBlock> for Error {
+    fn from(err: InvalidBlock) -> Self {
+        Error::InvalidBlock(err)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Error::Validation(err)
+    }
+}
+
+impl From<MempoolError> for Error {
+    fn from(err: MempoolError) -> Self {
+        Error::Mempool(err)
+    }
+}
+
+impl From<SealError> for Error {
+    fn from(err: SealError) -> Self {
+        Error::Consensus(err)
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(err: sled::Error) -> Self {
+        Error::Storage(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Error::Serialization(err)
+    }
+}
+
+impl From<ArchiveError> for Error {
+    fn from(err: ArchiveError) -> Self {
+        Error::Archive(err)
+    }
+}
+
+impl From<HexError> for Error {
+    fn from(err: HexError) -> Self {
+        Error::Hex(err)
+    }
+}
+
+impl From<SystemTimeError> for Error {
+    fn from(err: SystemTimeError) -> Self {
+        Error::Clock(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_sources_are_chained() {
+        let clock = UNIX_EPOCH
+            .duration_since(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap_err();
+        let err = Error::from(clock);
+        assert!(err.to_string().starts_with("system clock error"));
+        assert!(err.source().is_some());
+
+        let err = Error::from(ValidationError {
+            height: 3,
+            reason: InvalidBlock::BrokenLink,
+        });
+        let reason = err.source().and_then(|source| source.source()).unwrap();
+        assert_eq!(reason.to_string(), InvalidBlock::BrokenLink.to_string());
+        assert!(Error::MissingChain.source().is_none());
+    }
+}
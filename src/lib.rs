@@ -0,0 +1,36 @@
+//! A small proof-of-work blockchain with pluggable consensus.
+//!
+//! - `core`: blocks, transactions, the chain with its fork choice and ledger state, mining,
+//!   consensus engines and the mempool.
+//! - `storage`: `BlockchainManager`, which keeps a chain in a `ChainStore`: a sled database, an
+//!   append-only file or memory, the migrations that upgrade stores written with an older
+//!   schema, and the export and import of chains as JSON, CBOR or bincode archives.
+//! - `utils`: hex encoding, keypairs and addresses.
+//! - `error`: the `Error` type returned throughout the public API.
+//!
+//! The library never writes to stdout; progress and chain changes are reported through
+//! callbacks such as the one passed to `Miner::mine` and the events from
+//! `Blockchain::subscribe`.
+
+pub mod core;
+pub mod error;
+pub mod storage;
+pub mod utils;
+
+pub use core::block::Block;
+pub use core::block_header::BlockHeader;
+pub use core::blockchain::{BlockStatus, Blockchain};
+pub use core::consensus::{Consensus, ConsensusEngine};
+pub use core::events::ChainEvent;
+pub use core::mempool::Mempool;
+pub use core::miner::{CancelToken, Miner, MiningOutcome, MiningStats};
+pub use core::transaction::Transaction;
+pub use core::utxo::LedgerMode;
+pub use core::validation::{InvalidBlock, ValidationError};
+pub use error::{Error, Result};
+pub use storage::blockchain_manager::{BlockchainManager, OpenOptions};
+pub use storage::flat_file::FlatFileStore;
+pub use storage::memory::MemoryStore;
+pub use storage::sled_store::SledStore;
+pub use storage::store::ChainStore;
+pub use utils::crypto::Keypair;
@@ -1,53 +1,311 @@
-mod core;
-mod utils;
-use core::blockchain_manager::BlockchainManager;
-use rand::distr::{Distribution, Uniform};
-use std::io;
-use utils::hash::bytes_to_hex_string;
+use rust_blockchain::core::consensus::poa::ProofOfAuthority;
+use rust_blockchain::core::consensus::pos::{ProofOfStake, Validator};
+use rust_blockchain::core::consensus::seal_signer;
+use rust_blockchain::core::mempool::{DEFAULT_MAX_BLOCK_BYTES, DEFAULT_MAX_BYTES};
+use rust_blockchain::core::target::{difficulty_from_target, target_from_compact};
+use rust_blockchain::core::utxo::TxOutput;
+use rust_blockchain::storage::archive::ArchiveFormat;
+use rust_blockchain::storage::migration::{self, SCHEMA_VERSION};
+use rust_blockchain::utils::crypto::is_valid_address;
+use rust_blockchain::{
+    Blockchain, BlockchainManager, CancelToken, ChainEvent, ChainStore, Consensus, Error,
+    FlatFileStore, Keypair, LedgerMode, Mempool, Miner, MiningOutcome, OpenOptions, SledStore,
+    Transaction,
+};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
 
 fn main() {
-    let mut rng = rand::rng();
-    let mut blockchain_manager = match BlockchainManager::new("blockchain_db") {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    // The node key receives the block rewards and seals proof-of-authority and proof-of-stake
+    // blocks, so it is kept in a file and reused by every run.
+    let key_path = match flag_value(&args, "--key") {
+        None => "node.key",
+        Some(Some(path)) => path,
+        Some(None) => {
+            println!("--key expects the path of the node key file.");
+            return;
+        }
+    };
+    let wallet = match Keypair::load_or_create(key_path) {
+        Ok(keypair) => Arc::new(keypair),
+        Err(err) => {
+            println!("Failed to load the node key from {}: {}", key_path, err);
+            return;
+        }
+    };
+    // A new proof-of-authority or proof-of-stake chain is sealed by this node's wallet alone.
+    let consensus = match args.iter().position(|arg| arg == "--consensus") {
+        Some(index) => match args.get(index + 1).map(String::as_str) {
+            Some("pow") => Consensus::default(),
+            Some("poa") => Consensus::Authority(ProofOfAuthority {
+                authorities: vec![wallet.address()],
+            }),
+            Some("pos") => Consensus::Stake(ProofOfStake {
+                validators: vec![Validator {
+                    address: wallet.address(),
+                    stake: 1,
+                }],
+            }),
+            _ => {
+                println!("--consensus expects pow, poa or pos.");
+                return;
+            }
+        },
+        None => Consensus::default(),
+    };
+    let options = OpenOptions {
+        repair: has_flag("--repair"),
+        ledger: if has_flag("--utxo") {
+            LedgerMode::Utxo
+        } else {
+            LedgerMode::Account
+        },
+        consensus,
+        ..OpenOptions::default()
+    };
+    let format = match flag_value(&args, "--format") {
+        None | Some(Some("json")) => ArchiveFormat::Json,
+        Some(Some("cbor")) => ArchiveFormat::Cbor,
+        Some(Some("bincode")) => ArchiveFormat::Bincode,
+        Some(_) => {
+            println!("--format expects json, cbor or bincode.");
+            return;
+        }
+    };
+    let import = match flag_value(&args, "--import") {
+        Some(None) => {
+            println!("--import expects the path of an archive.");
+            return;
+        }
+        import => import.flatten(),
+    };
+    let store = match args.iter().position(|arg| arg == "--store") {
+        Some(index) => args.get(index + 1).map(String::as_str),
+        None => Some("sled"),
+    };
+    match store {
+        Some("sled") if has_flag("--migrate-dry-run") => {
+            dry_run_migration(SledStore::open("blockchain_db"))
+        }
+        Some("file") if has_flag("--migrate-dry-run") => {
+            dry_run_migration(FlatFileStore::open("blockchain_db.log"))
+        }
+        Some("sled") => run(
+            open(SledStore::open("blockchain_db"), options, import, format),
+            &args,
+            wallet,
+            format,
+        ),
+        Some("file") => run(
+            open(
+                FlatFileStore::open("blockchain_db.log"),
+                options,
+                import,
+                format,
+            ),
+            &args,
+            wallet,
+            format,
+        ),
+        _ => println!("--store expects sled or file."),
+    }
+}
+
+/// Opens the chain in the store, or with `--import`, restores it from an archive.
+fn open<S: ChainStore>(
+    store: Result<S, Error>,
+    options: OpenOptions,
+    import: Option<&str>,
+    format: ArchiveFormat,
+) -> Result<BlockchainManager<S>, Error> {
+    let store = store?;
+    match import {
+        Some(path) => {
+            let file = File::open(path)?;
+            BlockchainManager::from_archive(store, file, format, options)
+        }
+        None => BlockchainManager::with_store(store, options),
+    }
+}
+
+/// Reports the migrations that opening the store would run, without changing it.
+fn dry_run_migration<S: ChainStore>(store: Result<S, Error>) {
+    let report = store.and_then(|store| migration::migrate(&store, true));
+    match report {
+        Ok(report) => match report.from {
+            None => println!("The database holds no blockchain."),
+            Some(_) if report.steps.is_empty() => {
+                println!("The database is at schema version {}.", SCHEMA_VERSION)
+            }
+            Some(from) => {
+                println!(
+                    "Migrating from schema version {} to {} would run:",
+                    from, SCHEMA_VERSION
+                );
+                for step in report.steps {
+                    println!(" - {}", step);
+                }
+            }
+        },
+        Err(err) => println!("The migration would fail: {}", err),
+    }
+}
+
+/// Runs the menu on the chain opened from the selected store.
+fn run<S: ChainStore>(
+    opened: Result<BlockchainManager<S>, Error>,
+    args: &[String],
+    wallet: Arc<Keypair>,
+    format: ArchiveFormat,
+) {
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    let mut blockchain_manager = match opened {
         Ok(blockchain_manager) => blockchain_manager,
         Err(err) => {
             println!("Failed to initialize blockchain manager: {}", err);
+            if let Error::Validation(_) = err {
+                println!("Run with --repair to truncate the chain to its last valid block.");
+            }
             return;
         }
     };
-    let mut blockchain = blockchain_manager.get_blockchain();
+    let chain = blockchain_manager.blockchain();
+    println!(
+        "Blockchain loaded from storage. Current block height: {}",
+        chain.chain.len()
+    );
+    if let [genesis] = chain.chain.as_slice() {
+        println!("Genesis block:");
+        println!("Hash: {}", genesis.hash);
+        for tx in &genesis.transactions {
+            println!("Transaction: {}", tx);
+        }
+        println!("Nonce: {}", genesis.header.nonce);
+    }
+    if has_flag("--rebuild-ledger") {
+        match blockchain_manager.rebuild_ledger() {
+            Ok(()) => println!("Ledger state rebuilt from the stored blocks."),
+            Err(err) => println!("Failed to rebuild the ledger state: {}", err),
+        }
+    }
+    if has_flag("--import") {
+        println!("Archive imported.");
+        return;
+    }
+    if let Some(path) = flag_value(args, "--export") {
+        let heights = |flag| match flag_value(args, flag) {
+            None => Some(None),
+            Some(height) => height.and_then(|height| height.parse().ok()).map(Some),
+        };
+        let (Some(from), Some(to)) = (heights("--from"), heights("--to")) else {
+            println!("--from and --to expect a block height.");
+            return;
+        };
+        let Some(path) = path else {
+            println!("--export expects the path of an archive.");
+            return;
+        };
+        let exported = File::create(path).map_err(Error::from).and_then(|file| {
+            let heights = from.unwrap_or(0)..=to.unwrap_or(usize::MAX);
+            blockchain_manager.export(BufWriter::new(file), format, heights)
+        });
+        match exported {
+            Ok(count) => println!("Exported {} blocks to {}.", count, path),
+            Err(err) => println!("Failed to export the chain: {}", err),
+        }
+        return;
+    }
+    let miner = match args.iter().position(|arg| arg == "--miner") {
+        Some(index) => match args.get(index + 1) {
+            Some(address) if is_valid_address(address) => address.clone(),
+            _ => {
+                println!("--miner expects a valid address.");
+                return;
+            }
+        },
+        None => wallet.address(),
+    };
+    println!("Wallet address: {}", wallet.address());
+    println!("Block rewards are paid to {}", miner);
+    blockchain_manager.set_signer(Arc::clone(&wallet));
+    if let Some(index) = args.iter().position(|arg| arg == "--threads") {
+        match args.get(index + 1).and_then(|threads| threads.parse().ok()) {
+            Some(threads) => blockchain_manager.set_miner(Miner::new(threads)),
+            None => {
+                println!("--threads expects a number of mining threads.");
+                return;
+            }
+        }
+    }
+    println!(
+        "Mining with {} threads",
+        blockchain_manager.blockchain().miner.threads()
+    );
+    let events = blockchain_manager.subscribe();
+    let mut mempool = Mempool::new(DEFAULT_MAX_BYTES);
     loop {
         show();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        match input.trim().parse() {
+        let choice = read_line().parse();
+        let blockchain = blockchain_manager.blockchain();
+        match choice {
             Ok(0) => {
-                blockchain_manager.blockchain = blockchain;
-                let _ = blockchain_manager.save();
-                println!("Exiting application. Blockchain saved.");
+                println!(
+                    "Exiting application. Blockchain saved, {} blocks.",
+                    blockchain.chain.len()
+                );
                 break;
             }
             Ok(1) => {
-                println!("Generating new block with random transactions...");
-                let mut transactions: Vec<String> = Vec::new();
-                let die = Uniform::new_inclusive(1, 100);
-                let num = die.unwrap().sample(&mut rng);
-                for i in 0..num {
-                    transactions.push(format!("transaction {}", i));
+                if mempool.is_empty() {
+                    println!("The mempool is empty; the block will only hold the coinbase.");
+                }
+                println!("Mining a new block from the mempool...");
+                let transactions = mempool.block_template(blockchain, DEFAULT_MAX_BLOCK_BYTES);
+                let count = transactions.len();
+                let outcome = blockchain_manager.mine_and_append(
+                    &miner,
+                    transactions,
+                    &CancelToken::new(),
+                    |stats| println!("Mining... {}", stats),
+                );
+                match outcome {
+                    Ok(MiningOutcome::Mined { block, stats }) => {
+                        println!("Block mined successfully. Hash: {} ({})", block.hash, stats);
+                        let blockchain = blockchain_manager.blockchain();
+                        handle_events(&events, &mut mempool, blockchain);
+                        mempool.prune(blockchain);
+                        println!(
+                            "New block with {} pending transactions mined and saved to the chain.",
+                            count
+                        );
+                    }
+                    Ok(MiningOutcome::Cancelled { stats }) => {
+                        println!("Mining cancelled after {}.", stats);
+                    }
+                    Err(err) => println!("Failed to add block: {}", err),
                 }
-                let _ = blockchain.add_block(transactions);
-                println!("New block successfully mined and added to the chain.");
             }
             Ok(2) => {
-                let blockchain_iter = blockchain.iter();
-                blockchain_iter.for_each(|block| {
+                let blockchain_iter = blockchain.iter().zip(blockchain.cumulative_work());
+                blockchain_iter.for_each(|(block, work)| {
                     println!("[Block Details]");
                     println!("Timestamp: {}", block.header.timestamp);
-                    println!(
-                        "Previous Hash: {}",
-                        bytes_to_hex_string(&block.header.prev_hash)
-                    );
-                    println!("Current Hash: {}", bytes_to_hex_string(&block.hash));
+                    println!("Previous Hash: {}", block.header.prev_hash);
+                    println!("Current Hash: {}", block.hash);
                     println!("Nonce: {}", block.header.nonce);
+                    println!("Target Bits: {:#010x}", block.header.bits);
+                    if let Some(target) = target_from_compact(block.header.bits) {
+                        println!("Difficulty: {} bits", difficulty_from_target(target));
+                    }
+                    if let Some(signer) = seal_signer(block) {
+                        println!("Sealed by: {}", signer);
+                    }
+                    println!("Chain Work: {}", work);
                     println!("Transaction Count: {}", block.transactions.len());
                     println!("Transactions:");
                     for (i, tx) in block.transactions.iter().enumerate() {
@@ -56,14 +314,151 @@ fn main() {
                     println!("-----------------------------");
                 });
             }
+            Ok(3) => {
+                let tx = match build_transaction(blockchain, &mempool, &wallet) {
+                    Ok(Some(tx)) => tx,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        println!("Failed to build transaction: {}", err);
+                        continue;
+                    }
+                };
+                match mempool.add(tx, blockchain) {
+                    Ok(()) => println!("Transaction added to the mempool."),
+                    Err(err) => println!("Failed to submit transaction: {}", err),
+                }
+            }
+            Ok(4) => {
+                println!(
+                    "Wallet balance: {}",
+                    blockchain.balance_of(&wallet.address())
+                );
+                println!("Chain work: {}", blockchain.chain_work());
+                println!(
+                    "Pending transactions: {} ({} bytes)",
+                    mempool.len(),
+                    mempool.size_bytes()
+                );
+                for tx in mempool.transactions() {
+                    println!(" - {}", tx);
+                }
+            }
             _ => {}
         }
     }
 }
+
+/// Prints the changes to the active chain and returns the transactions of disconnected blocks
+/// to the mempool.
+fn handle_events(events: &Receiver<ChainEvent>, mempool: &mut Mempool, blockchain: &Blockchain) {
+    for event in events.try_iter() {
+        println!("{}", event);
+        if let ChainEvent::BlockDisconnected { block, .. } = event {
+            // The mempool refuses the coinbase and anything the new branch already includes.
+            for tx in block.transactions {
+                let _ = mempool.add(tx, blockchain);
+            }
+        }
+    }
+}
+
+/// Returns `None` if `flag` was not passed, or the argument that follows it.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<Option<&'a str>> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(index + 1).map(String::as_str))
+}
+
+fn read_line() -> String {
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
+
+fn read_amount(prompt: &str) -> Option<u64> {
+    println!("{}", prompt);
+    let amount = read_line().parse().ok();
+    if amount.is_none() {
+        println!("Please enter a whole number.");
+    }
+    amount
+}
+
+/// Prompts for a memo or a transfer from `wallet` and builds the signed transaction, or returns
+/// `None` if the input is invalid.
+fn build_transaction(
+    blockchain: &Blockchain,
+    mempool: &Mempool,
+    wallet: &Keypair,
+) -> Result<Option<Transaction>, Error> {
+    println!("Recipient address (leave empty for a memo-only transaction): ");
+    let recipient = read_line();
+    if recipient.is_empty() {
+        println!("Memo: ");
+        return Transaction::memo(read_line()).map(Some);
+    }
+    let Some(amount) = read_amount("Amount: ") else {
+        return Ok(None);
+    };
+    let Some(fee) = read_amount("Fee: ") else {
+        return Ok(None);
+    };
+    let address = wallet.address();
+    match blockchain.ledger {
+        LedgerMode::Account => {
+            let nonce = mempool.next_nonce(blockchain, &address);
+            let mut tx = Transaction::new(&address, &recipient, amount, nonce)?.with_fee(fee);
+            tx.sign(wallet);
+            Ok(Some(tx))
+        }
+        LedgerMode::Utxo => {
+            let pending: BTreeSet<_> = mempool
+                .transactions()
+                .flat_map(|tx| tx.inputs.iter().map(|input| &input.previous_output))
+                .collect();
+            let needed = amount.saturating_add(fee);
+            let mut inputs = Vec::new();
+            let mut total: u64 = 0;
+            for (outpoint, output) in blockchain.utxo_set().unspent_for(&address) {
+                if total >= needed {
+                    break;
+                }
+                if !pending.contains(outpoint) {
+                    inputs.push(outpoint.clone());
+                    total = total.saturating_add(output.value);
+                }
+            }
+            if total < needed {
+                println!(
+                    "The wallet only has {} unspent and unreserved coins.",
+                    total
+                );
+                return Ok(None);
+            }
+            let mut outputs = vec![TxOutput {
+                value: amount,
+                locking_key: recipient,
+            }];
+            if total > needed {
+                outputs.push(TxOutput {
+                    value: total - needed,
+                    locking_key: address,
+                });
+            }
+            let mut tx = Transaction::utxo(inputs, outputs)?.with_fee(fee);
+            for index in 0..tx.inputs.len() {
+                tx.sign_input(index, wallet);
+            }
+            Ok(Some(tx))
+        }
+    }
+}
+
 fn show() {
     println!("Blockchain CLI - Main Menu");
-    println!("1. Generate new block");
+    println!("1. Mine new block from the mempool");
     println!("2. Display blockchain");
-    println!("0. Exit and save");
+    println!("3. Submit transaction");
+    println!("4. Show wallet and mempool");
+    println!("0. Exit");
     println!("Enter your choice: ");
 }
@@ -0,0 +1,402 @@
+use super::blockchain_manager::{BlockchainManager, ChainParams, OpenOptions};
+use super::migration::schema_version;
+use super::store::ChainStore;
+use crate::core::block::Block;
+use crate::core::blockchain::BlockStatus;
+use crate::error::Error;
+use bincode::{deserialize, serialize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::{Bound, RangeBounds};
+
+/// Version of the archive header written by `BlockchainManager::export`.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// How the items of an archive are encoded.
+///
+/// - `Json`: one JSON object per line (NDJSON); hashes are hex strings.
+/// - `Cbor`: a sequence of CBOR items, one after the other.
+/// - `Bincode`: each item's bincode encoding, preceded by its length as a little-endian `u32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Json,
+    Cbor,
+    Bincode,
+}
+
+/// The reason an archive could not be written or read. Errors of the underlying reader or
+/// writer and of bincode are reported as `Error::Io` and `Error::Serialization`.
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// A JSON line could not be encoded or decoded.
+    Json(serde_json::Error),
+    /// A CBOR item could not be encoded.
+    CborEncode(ciborium::ser::Error<io::Error>),
+    /// A CBOR item could not be decoded.
+    CborDecode(ciborium::de::Error<io::Error>),
+    /// The archive is empty.
+    MissingHeader,
+    /// The archive was written with a newer header version than this version can read.
+    UnsupportedVersion(u32),
+    /// A new chain can only be started from an archive whose first block is the genesis
+    /// block.
+    NotFromGenesis,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Json(err) => write!(f, "invalid JSON item: {}", err),
+            ArchiveError::CborEncode(err) => write!(f, "cannot encode CBOR item: {}", err),
+            ArchiveError::CborDecode(err) => write!(f, "invalid CBOR item: {}", err),
+            ArchiveError::MissingHeader => write!(f, "the archive is empty"),
+            ArchiveError::UnsupportedVersion(version) => write!(
+                f,
+                "archive version {} is newer than the supported version {}",
+                version, ARCHIVE_VERSION
+            ),
+            ArchiveError::NotFromGenesis => {
+                write!(f, "the archive does not start at the genesis block")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ArchiveError::Json(err) => Some(err),
+            ArchiveError::CborEncode(err) => Some(err),
+            ArchiveError::CborDecode(err) => Some(err),
+            ArchiveError::MissingHeader
+            | ArchiveError::UnsupportedVersion(_)
+            | ArchiveError::NotFromGenesis => None,
+        }
+    }
+}
+
+/// The first item of an archive: the chain parameters and the height of the first block.
+#[derive(Deserialize, Serialize)]
+struct Header {
+    version: u32,
+    start: u64,
+    params: ChainParams,
+}
+
+/// # Methods
+///
+/// - `export(&self, writer: impl Write, format: ArchiveFormat, heights: impl
+///   RangeBounds<usize>) -> Result<usize, Error>`: Writes the blocks of the active chain at
+///   `heights`, clamped to the chain, and returns how many were written. The archive starts
+///   with a header holding the chain parameters and the height of the first block.
+///
+/// - `import(&mut self, reader: impl Read, format: ArchiveFormat) -> Result<usize, Error>`:
+///   Reads an archive and appends each block with `append_block`, which validates it and
+///   saves it before the next one is read. Blocks the chain already holds are skipped, so an
+///   interrupted import can be run again. Returns the number of new blocks; at the first
+///   block that is rejected, the blocks before it stay appended and the error is returned.
+///
+/// - `from_archive(store: S, reader: impl Read, format: ArchiveFormat, options: OpenOptions)
+///   -> Result<Self, Error>`: Opens the chain in `store` like `with_store` and imports the
+///   archive into it. If the store holds no chain, the chain is started from the genesis
+///   block and the parameters of the archive instead of a new genesis block.
+impl<S: ChainStore> BlockchainManager<S> {
+    pub fn export(
+        &self,
+        mut writer: impl Write,
+        format: ArchiveFormat,
+        heights: impl RangeBounds<usize>,
+    ) -> Result<usize, Error> {
+        let chain = &self.blockchain().chain;
+        let start = match heights.start_bound() {
+            Bound::Included(&height) => height,
+            Bound::Excluded(&height) => height.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(chain.len());
+        let end = match heights.end_bound() {
+            Bound::Included(&height) => height.saturating_add(1),
+            Bound::Excluded(&height) => height,
+            Bound::Unbounded => chain.len(),
+        }
+        .clamp(start, chain.len());
+
+        let header = Header {
+            version: ARCHIVE_VERSION,
+            start: start as u64,
+            params: ChainParams::of(self.blockchain()),
+        };
+        write_item(&mut writer, format, &header)?;
+        for block in &chain[start..end] {
+            write_item(&mut writer, format, block)?;
+        }
+        writer.flush()?;
+        Ok(end - start)
+    }
+
+    pub fn import(&mut self, reader: impl Read, format: ArchiveFormat) -> Result<usize, Error> {
+        let mut reader = BufReader::new(reader);
+        read_header(&mut reader, format)?;
+        self.append_all(&mut reader, format)
+    }
+
+    pub fn from_archive(
+        store: S,
+        reader: impl Read,
+        format: ArchiveFormat,
+        options: OpenOptions,
+    ) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+        let header = read_header(&mut reader, format)?;
+        let mut manager = if schema_version(&store)?.is_some() {
+            Self::with_store(store, options)?
+        } else {
+            let genesis = match read_item::<Block>(&mut reader, format)? {
+                Some(genesis) if header.start == 0 => genesis,
+                _ => return Err(ArchiveError::NotFromGenesis.into()),
+            };
+            let mut blockchain = header.params.into_blockchain(vec![genesis], Vec::new());
+            blockchain.validate()?;
+            blockchain.rebuild_state()?;
+            Self::start(store, blockchain)?
+        };
+        manager.append_all(&mut reader, format)?;
+        Ok(manager)
+    }
+
+    fn append_all(
+        &mut self,
+        reader: &mut impl BufRead,
+        format: ArchiveFormat,
+    ) -> Result<usize, Error> {
+        let mut appended = 0;
+        while let Some(block) = read_item::<Block>(reader, format)? {
+            if self.append_block(block)? != BlockStatus::Known {
+                appended += 1;
+            }
+        }
+        Ok(appended)
+    }
+}
+
+fn write_item<T: Serialize>(
+    writer: &mut impl Write,
+    format: ArchiveFormat,
+    item: &T,
+) -> Result<(), Error> {
+    match format {
+        ArchiveFormat::Json => {
+            serde_json::to_writer(&mut *writer, item).map_err(ArchiveError::Json)?;
+            writer.write_all(b"\n")?;
+        }
+        ArchiveFormat::Cbor => {
+            ciborium::into_writer(item, &mut *writer).map_err(ArchiveError::CborEncode)?;
+        }
+        ArchiveFormat::Bincode => {
+            let bytes = serialize(item)?;
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the next item, or returns `None` at the end of the archive.
+fn read_item<T: DeserializeOwned>(
+    reader: &mut impl BufRead,
+    format: ArchiveFormat,
+) -> Result<Option<T>, Error> {
+    match format {
+        ArchiveFormat::Json => {
+            let mut line = String::new();
+            while line.trim().is_empty() {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+            }
+            Ok(Some(
+                serde_json::from_str(&line).map_err(ArchiveError::Json)?,
+            ))
+        }
+        _ if reader.fill_buf()?.is_empty() => Ok(None),
+        ArchiveFormat::Cbor => Ok(Some(
+            ciborium::from_reader(&mut *reader).map_err(ArchiveError::CborDecode)?,
+        )),
+        ArchiveFormat::Bincode => {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            let len = u32::from_le_bytes(len) as usize;
+            // Read through `take` so that a corrupt length cannot allocate more than the
+            // archive holds.
+            let mut bytes = Vec::new();
+            reader.take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() < len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            Ok(Some(deserialize(&bytes)?))
+        }
+    }
+}
+
+fn read_header(reader: &mut impl BufRead, format: ArchiveFormat) -> Result<Header, Error> {
+    let header: Header = read_item(reader, format)?.ok_or(ArchiveError::MissingHeader)?;
+    if header.version > ARCHIVE_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(header.version).into());
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::Blockchain;
+    use crate::core::miner::CancelToken;
+    use crate::core::transaction::Transaction;
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::hash::Hash256;
+
+    const MINER: &str = "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450";
+
+    fn manager_with_blocks(count: usize) -> BlockchainManager<MemoryStore> {
+        let chain = Blockchain::new(1).unwrap();
+        let mut manager = BlockchainManager::start(MemoryStore::new(), chain).unwrap();
+        for i in 0..count {
+            let memo = Transaction::memo(format!("block {}", i)).unwrap();
+            manager
+                .mine_and_append(MINER, vec![memo], &CancelToken::new(), |_| {})
+                .unwrap();
+        }
+        manager
+    }
+
+    fn hashes(manager: &BlockchainManager<impl ChainStore>) -> Vec<Hash256> {
+        manager
+            .blockchain()
+            .iter()
+            .map(|block| block.hash)
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_round_trip_in_every_format() {
+        let source = manager_with_blocks(3);
+        for format in [
+            ArchiveFormat::Json,
+            ArchiveFormat::Cbor,
+            ArchiveFormat::Bincode,
+        ] {
+            let mut archive = Vec::new();
+            assert_eq!(source.export(&mut archive, format, ..).unwrap(), 4);
+            let restored = BlockchainManager::from_archive(
+                MemoryStore::new(),
+                archive.as_slice(),
+                format,
+                OpenOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(hashes(&restored), hashes(&source));
+            assert_eq!(
+                restored.blockchain().balance_of(MINER),
+                source.blockchain().balance_of(MINER)
+            );
+            assert_eq!(
+                restored.block_at(3).unwrap().unwrap().hash,
+                hashes(&source)[3]
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_by_height_range() {
+        let source = manager_with_blocks(3);
+        let mut head = Vec::new();
+        source.export(&mut head, ArchiveFormat::Json, ..=1).unwrap();
+        assert_eq!(String::from_utf8_lossy(&head).lines().count(), 3);
+        let mut manager = BlockchainManager::from_archive(
+            MemoryStore::new(),
+            head.as_slice(),
+            ArchiveFormat::Json,
+            OpenOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(manager.blockchain().chain.len(), 2);
+
+        // Heights 1 and 2 again: the known block is skipped.
+        let mut tail = Vec::new();
+        assert_eq!(
+            source.export(&mut tail, ArchiveFormat::Cbor, 1..3).unwrap(),
+            2
+        );
+        let appended = manager.import(tail.as_slice(), ArchiveFormat::Cbor);
+        assert_eq!(appended.unwrap(), 1);
+        assert_eq!(manager.blockchain().chain.len(), 3);
+
+        // Height 3 does not connect to a different genesis block.
+        let mut last = Vec::new();
+        source
+            .export(&mut last, ArchiveFormat::Bincode, 3..)
+            .unwrap();
+        let mut other = manager_with_blocks(0);
+        assert!(
+            other
+                .import(last.as_slice(), ArchiveFormat::Bincode)
+                .is_err()
+        );
+        assert!(matches!(
+            BlockchainManager::from_archive(
+                MemoryStore::new(),
+                last.as_slice(),
+                ArchiveFormat::Bincode,
+                OpenOptions::default(),
+            ),
+            Err(Error::Archive(ArchiveError::NotFromGenesis))
+        ));
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_block() {
+        let source = manager_with_blocks(2);
+        let mut archive = Vec::new();
+        source
+            .export(&mut archive, ArchiveFormat::Json, ..)
+            .unwrap();
+        let forged = String::from_utf8(archive)
+            .unwrap()
+            .replace("block 1", "forged!");
+        let store = MemoryStore::new();
+        let result = BlockchainManager::from_archive(
+            store.clone(),
+            forged.as_bytes(),
+            ArchiveFormat::Json,
+            OpenOptions::default(),
+        );
+        assert!(result.is_err());
+
+        // The blocks before the forged one were validated and saved.
+        let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(hashes(&manager), hashes(&source)[..2]);
+
+        let mut truncated = Vec::new();
+        source
+            .export(&mut truncated, ArchiveFormat::Bincode, ..)
+            .unwrap();
+        truncated.pop();
+        assert!(matches!(
+            BlockchainManager::from_archive(
+                MemoryStore::new(),
+                truncated.as_slice(),
+                ArchiveFormat::Bincode,
+                OpenOptions::default(),
+            ),
+            Err(Error::Io(_))
+        ));
+        let mut copy = manager_with_blocks(0);
+        assert!(matches!(
+            copy.import(&[][..], ArchiveFormat::Cbor),
+            Err(Error::Archive(ArchiveError::MissingHeader))
+        ));
+    }
+}
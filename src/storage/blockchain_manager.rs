@@ -0,0 +1,1516 @@
+use crate::core::block::Block;
+use crate::core::blockchain::{BlockStatus, Blockchain};
+use crate::core::consensus::Consensus;
+use crate::core::events::ChainEvent;
+use crate::core::miner::{CancelToken, Miner, MiningOutcome, MiningStats};
+use crate::core::reward::RewardSchedule;
+use crate::core::state::WorldState;
+use crate::core::transaction::Transaction;
+use crate::core::utxo::{LedgerMode, OutPoint, UtxoSet};
+use crate::core::validation::ValidationError;
+use crate::error::Error;
+use crate::storage::migration::{self, SCHEMA_VERSION, SCHEMA_VERSION_KEY};
+use crate::storage::sled_store::SledStore;
+use crate::storage::store::{ChainStore, Table, WriteBatch};
+use crate::utils::crypto::Keypair;
+use crate::utils::hash::Hash256;
+use bincode::{deserialize, serialize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Key of the whole bincode-encoded chain in databases written before the per-block layout.
+pub(super) const BLOCKCHAIN_KEY: &str = "blockchain";
+/// Key of the whole bincode-encoded ledger state in databases written before
+/// `Table::Accounts`.
+const STATE_KEY: &str = "state";
+const STATE_TIP_KEY: &str = "state_tip";
+const UTXO_TIP_KEY: &str = "utxo_tip";
+pub(super) const TIP_KEY: &str = "tip";
+const PARAMS_KEY: &str = "params";
+pub(super) const QUARANTINE_PREFIX: &str = "quarantine/blockchain/";
+const DEFAULT_DIFFICULTY: u32 = 4;
+
+pub struct BlockchainManager<S: ChainStore = SledStore> {
+    store: S,
+    blockchain: Blockchain,
+    /// Whether `store` holds every block of `blockchain` and its ledger state.
+    synced: bool,
+}
+
+/// Controls how `BlockchainManager::open` treats missing or corrupt databases.
+///
+/// - `create_if_missing`: start a new chain when the database holds none (default `true`).
+/// - `repair`: instead of refusing a chain that fails validation, truncate it to the last
+///   valid height. A chain with a missing or undecodable block is truncated below it, and a
+///   new chain is started if the genesis block or the chain metadata cannot be read. The
+///   dropped blocks are backed up to a quarantine key first (default `false`).
+/// - `ledger`: the ledger mode of a newly created chain; a stored chain keeps its own mode
+///   (default `LedgerMode::Account`).
+/// - `reward`: the block reward schedule of a newly created chain; a stored chain keeps its
+///   own schedule (default `RewardSchedule::default()`).
+/// - `consensus`: the consensus engine of a newly created chain; a stored chain keeps its own
+///   (default `Consensus::default()`, proof of work).
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    pub create_if_missing: bool,
+    pub repair: bool,
+    pub ledger: LedgerMode,
+    pub reward: RewardSchedule,
+    pub consensus: Consensus,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            create_if_missing: true,
+            repair: false,
+            ledger: LedgerMode::Account,
+            reward: RewardSchedule::default(),
+            consensus: Consensus::default(),
+        }
+    }
+}
+
+/// The parameters of a stored chain, kept in the metadata tree next to its tip.
+#[derive(Deserialize, Serialize)]
+pub(super) struct ChainParams {
+    difficulty: u32,
+    ledger: LedgerMode,
+    consensus: Consensus,
+    reward: RewardSchedule,
+}
+
+impl ChainParams {
+    pub(super) fn of(blockchain: &Blockchain) -> Self {
+        Self {
+            difficulty: blockchain.difficulty,
+            ledger: blockchain.ledger,
+            consensus: blockchain.consensus.clone(),
+            reward: blockchain.reward,
+        }
+    }
+
+    /// Builds a blockchain with these parameters from stored blocks, without validating them.
+    pub(super) fn into_blockchain(self, chain: Vec<Block>, side_blocks: Vec<Block>) -> Blockchain {
+        let mut blockchain = Blockchain::from_parts(
+            self.difficulty,
+            self.ledger,
+            self.consensus,
+            chain,
+            side_blocks,
+        );
+        blockchain.reward = self.reward;
+        blockchain
+    }
+}
+
+/// Manages blockchain operations including persistence and retrieval
+///
+/// The `BlockchainManager` struct provides functionality to:
+/// - Load a blockchain from disk
+/// - Save blockchain state to disk
+/// - Access the current blockchain state
+/// - Append blocks, each written to the store before the call returns
+/// - Look up a saved block by hash or height without loading the chain
+///
+/// The manager keeps its data in a `ChainStore`: a sled database by default, or any other
+/// store passed to `with_store`. Every block, on the active chain or a side branch, is stored
+/// on its own in `Table::Blocks`, keyed by its hash. `Table::Heights` maps each height of the
+/// active chain, as a big-endian `u64`, to the hash of its block, and `Table::Meta` holds the
+/// height and hash of the tip, the chain parameters and the schema version. A database
+/// written with an older schema, such as the single-key layout, is upgraded by
+/// `migration::migrate` when it is opened, after a backup.
+///
+/// The ledger state of an account chain is kept in `Table::Accounts`, one balance and nonce
+/// per address, and the UTXO set of a UTXO chain in `Table::Utxo`, one entry per unspent
+/// output keyed by `OutPoint::to_key`.
+///
+/// Appending a block that extends the tip writes only that block, its height entry, the tip
+/// and the ledger entries the block changed, and a side block is written on its own, so the
+/// cost of an append does not grow with the chain or the ledger. A reorganization rewrites the
+/// height entries above the fork and compares the ledger tables with the replayed state, and
+/// the first append after a failed save falls back to `save`.
+///
+/// The chain only changes through `append_block` and `mine_and_append`, which commit each
+/// block together with the ledger state before they return, so a process killed at any point,
+/// by SIGINT or otherwise, loses at most the block it was working on. `blockchain` gives read
+/// access to the chain; the signer, the miner and the event subscribers are local settings
+/// that are never stored.
+impl BlockchainManager {
+    /// Creates a new `BlockchainManager` instance with the default `OpenOptions`
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - A string slice that holds the path to the database file
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, Error>` - A new BlockchainManager instance if successful, or an error if creation fails
+    ///
+    /// # Note
+    ///
+    /// If no existing blockchain is found in the database, a new blockchain with difficulty
+    /// level 4 will be created. A stored chain that cannot be decoded or fails validation is
+    /// refused rather than replaced.
+    pub fn new(db_path: &str) -> Result<Self, Error> {
+        Self::open(db_path, OpenOptions::default())
+    }
+
+    /// Opens the sled database at `db_path`, validating the stored chain before use
+    ///
+    /// # Note
+    ///
+    /// See `with_store` for how the stored chain is loaded, checked and repaired.
+    pub fn open(db_path: &str, options: OpenOptions) -> Result<Self, Error> {
+        Self::with_store(SledStore::open(db_path)?, options)
+    }
+}
+
+impl<S: ChainStore> BlockchainManager<S> {
+    /// Opens the chain kept in `store`, validating it before use
+    ///
+    /// # Note
+    ///
+    /// The ledger state saved alongside the chain is reused when it matches the chain tip;
+    /// otherwise it is rebuilt by replaying the blocks, and a chain that breaks the ledger
+    /// rules is treated as corrupt.
+    ///
+    /// A store written with an older schema version is migrated first, see
+    /// `migration::migrate`; one written by a newer version is refused with
+    /// `Error::UnsupportedSchema`.
+    ///
+    /// Undecodable bytes are always moved to a quarantine key before an error is returned.
+    /// With `repair` set, a chain that fails validation has its invalid blocks backed up to a
+    /// quarantine key, is truncated to the blocks before the first invalid height and saved.
+    pub fn with_store(store: S, options: OpenOptions) -> Result<Self, Error> {
+        let loaded =
+            migration::migrate(&store, false).and_then(|_| Self::load(&store, options.repair));
+        let blockchain = match loaded {
+            Ok(mut chain) => match Self::check(&store, &mut chain) {
+                Ok(()) => chain,
+                Err(Error::Validation(err)) if options.repair => Self::repair(&store, chain, &err)?,
+                Err(err) => return Err(err),
+            },
+            Err(Error::MissingChain) if options.create_if_missing => Self::create(&options)?,
+            Err(Error::Undecodable { .. } | Error::MissingBlock { .. }) if options.repair => {
+                Self::quarantine_indexed(&store, 0)?;
+                Self::create(&options)?
+            }
+            Err(err) => return Err(err),
+        };
+        let synced = options.repair || Self::is_saved(&store, &blockchain)?;
+        let manager = Self {
+            store,
+            blockchain,
+            synced,
+        };
+        if options.repair {
+            manager.save()?;
+        }
+        Ok(manager)
+    }
+
+    /// Keeps `blockchain` in `store`, which holds no chain yet, and saves it.
+    pub(super) fn start(store: S, blockchain: Blockchain) -> Result<Self, Error> {
+        let manager = Self {
+            store,
+            blockchain,
+            synced: true,
+        };
+        manager.save()?;
+        Ok(manager)
+    }
+
+    /// Whether the tip and the ledger tip of `store` are those of `blockchain`, as written by
+    /// the last save of the loaded chain.
+    fn is_saved(store: &S, blockchain: &Blockchain) -> Result<bool, Error> {
+        let Some(last) = blockchain.get_last_block() else {
+            return Ok(false);
+        };
+        let height = blockchain.chain.len();
+        let ledger_tip = match blockchain.ledger {
+            LedgerMode::Account => STATE_TIP_KEY,
+            LedgerMode::Utxo => UTXO_TIP_KEY,
+        };
+        let stored = |table: Table, key: &str, expected: (usize, Hash256)| -> Result<bool, Error> {
+            Ok(store.get(table, key.as_bytes())?.is_some_and(|data| {
+                deserialize::<(usize, Hash256)>(&data).is_ok_and(|tip| tip == expected)
+            }))
+        };
+        Ok(stored(Table::Meta, TIP_KEY, (height - 1, last.hash))?
+            && stored(Table::Default, ledger_tip, (height, last.hash))?)
+    }
+
+    fn create(options: &OpenOptions) -> Result<Blockchain, Error> {
+        let mut chain = Blockchain::with_consensus(
+            DEFAULT_DIFFICULTY,
+            options.ledger,
+            options.consensus.clone(),
+            &[],
+        )?;
+        chain.reward = options.reward;
+        Ok(chain)
+    }
+
+    /// Reads the active chain through the height index and every other stored block as a
+    /// side block; `Blockchain::submit_block` validates side blocks when a branch of them
+    /// would become active. With `repair` set, the chain ends below the first block that is missing
+    /// or cannot be decoded, and the indexed blocks above it are moved to a quarantine key.
+    fn load(store: &S, repair: bool) -> Result<Blockchain, Error> {
+        let tip = store
+            .get(Table::Meta, TIP_KEY.as_bytes())?
+            .ok_or(Error::MissingChain)?;
+        let (tip_height, _): (usize, Hash256) =
+            decode(store, Table::Meta, TIP_KEY.as_bytes(), tip)?;
+        let params = store
+            .get(Table::Meta, PARAMS_KEY.as_bytes())?
+            .ok_or(Error::MissingChain)?;
+        let params: ChainParams = decode(store, Table::Meta, PARAMS_KEY.as_bytes(), params)?;
+
+        let mut chain = Vec::with_capacity(tip_height + 1);
+        for height in 0..=tip_height {
+            match read_block_at(store, height) {
+                Ok(block) => chain.push(block),
+                Err(Error::Undecodable { .. } | Error::MissingBlock { .. })
+                    if repair && height > 0 =>
+                {
+                    Self::quarantine_indexed(store, height + 1)?;
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let indexed: BTreeSet<_> = store
+            .range(Table::Heights, &[])?
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        let mut side_blocks = Vec::new();
+        for (hash, data) in store.range(Table::Blocks, &[])? {
+            if indexed.contains(&hash) {
+                continue;
+            }
+            match decode(store, Table::Blocks, &hash, data) {
+                Ok(block) => side_blocks.push(block),
+                Err(Error::Undecodable { .. }) if repair => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(params.into_blockchain(chain, side_blocks))
+    }
+
+    fn check(store: &S, chain: &mut Blockchain) -> Result<(), Error> {
+        chain.validate()?;
+        let restored = match chain.ledger {
+            LedgerMode::Account => {
+                Self::load_state(store).is_some_and(|state| chain.restore_state(state))
+            }
+            LedgerMode::Utxo => {
+                Self::load_utxo_set(store).is_some_and(|utxos| chain.restore_utxo_set(utxos))
+            }
+        };
+        if restored {
+            Ok(())
+        } else {
+            chain.rebuild_state()
+        }
+    }
+
+    fn load_state(store: &S) -> Option<WorldState> {
+        let Some(tip) = store.get(Table::Default, STATE_TIP_KEY.as_bytes()).ok()? else {
+            let state = store.get(Table::Default, STATE_KEY.as_bytes()).ok()??;
+            return deserialize(&state).ok();
+        };
+        let (height, tip): (usize, Hash256) = deserialize(&tip).ok()?;
+        let accounts = store
+            .range(Table::Accounts, &[])
+            .ok()?
+            .into_iter()
+            .map(|(key, value)| Some((String::from_utf8(key).ok()?, deserialize(&value).ok()?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(WorldState::from_accounts(accounts, height, tip))
+    }
+
+    fn load_utxo_set(store: &S) -> Option<UtxoSet> {
+        let tip = store.get(Table::Default, UTXO_TIP_KEY.as_bytes()).ok()??;
+        let (height, tip): (usize, Hash256) = deserialize(&tip).ok()?;
+        let outputs = store
+            .range(Table::Utxo, &[])
+            .ok()?
+            .into_iter()
+            .map(|(key, value)| Some((OutPoint::from_key(&key)?, deserialize(&value).ok()?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(UtxoSet::from_outputs(outputs, height, tip))
+    }
+
+    fn repair(
+        store: &S,
+        mut chain: Blockchain,
+        err: &ValidationError,
+    ) -> Result<Blockchain, Error> {
+        Self::quarantine(store, &chain.chain[err.height..])?;
+        if err.height == 0 {
+            let mut fresh =
+                Blockchain::with_consensus(chain.difficulty, chain.ledger, chain.consensus, &[])?;
+            fresh.reward = chain.reward;
+            return Ok(fresh);
+        }
+        chain.chain.truncate(err.height);
+        chain.rebuild_state()?;
+        Ok(chain)
+    }
+
+    /// Backs up `blocks` to a quarantine key, so that saving a shorter chain does not lose
+    /// them.
+    fn quarantine(store: &S, blocks: &[Block]) -> Result<(), Error> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let mut batch = WriteBatch::new();
+        batch.put(
+            Table::Default,
+            timestamped_key(QUARANTINE_PREFIX),
+            serialize(blocks)?,
+        );
+        store.commit(batch)
+    }
+
+    /// Backs up the blocks of the height index from `from` up, which the next save drops.
+    /// Blocks that cannot be decoded are quarantined on their own by `decode`.
+    fn quarantine_indexed(store: &S, from: usize) -> Result<(), Error> {
+        let mut blocks = Vec::new();
+        for (_, hash) in store.range(Table::Heights, &height_key(from))? {
+            let Some(data) = store.get(Table::Blocks, &hash)? else {
+                continue;
+            };
+            match decode(store, Table::Blocks, &hash, data) {
+                Ok(block) => blocks.push(block),
+                Err(Error::Undecodable { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Self::quarantine(store, &blocks)
+    }
+
+    /// Returns the store the chain is kept in
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the current blockchain
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.blockchain
+    }
+
+    /// Returns a clone of the current blockchain
+    ///
+    /// # Returns
+    ///
+    /// * `Blockchain` - A copy of the current blockchain state
+    pub fn get_blockchain(&self) -> Blockchain {
+        self.blockchain.clone()
+    }
+
+    /// Sets the key that seals blocks on a proof-of-authority or proof-of-stake chain
+    pub fn set_signer(&mut self, signer: Arc<Keypair>) {
+        self.blockchain.signer = Some(signer);
+    }
+
+    /// Sets the miner used by `mine_and_append` on a proof-of-work chain
+    pub fn set_miner(&mut self, miner: Miner) {
+        self.blockchain.miner = miner;
+    }
+
+    /// Returns a receiver for the events of the chain, as `Blockchain::subscribe`
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        self.blockchain.subscribe()
+    }
+
+    /// Reads a saved block, on the active chain or a side branch, by its hash
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Block>, Error>` - The block, or `None` if no saved block has this hash
+    pub fn block_by_hash(&self, hash: &Hash256) -> Result<Option<Block>, Error> {
+        Ok(self
+            .store
+            .get(Table::Blocks, hash.as_bytes())?
+            .map(|data| deserialize(&data))
+            .transpose()?)
+    }
+
+    /// Reads the block at `height` of the active chain as of the last save
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Block>, Error>` - The block, or `None` if the saved chain is shorter
+    pub fn block_at(&self, height: usize) -> Result<Option<Block>, Error> {
+        match self.store.get(Table::Heights, &height_key(height))? {
+            Some(hash) => self.block_by_hash(&Hash256::from_slice(&hash)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Replays the blocks of the chain to rebuild its ledger state, including the UTXO set
+    /// of a UTXO chain, and saves the result
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Ok(()) if the rebuilt state was saved, or
+    ///   `Error::Validation` if a block breaks the ledger rules
+    pub fn rebuild_ledger(&mut self) -> Result<(), Error> {
+        self.synced = false;
+        self.blockchain.rebuild_state()?;
+        self.save()?;
+        self.synced = true;
+        Ok(())
+    }
+
+    /// Adds a block produced elsewhere to the chain and saves it
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BlockStatus, Error>` - Where the block ended up, as for
+    ///   `Blockchain::submit_block`, once it is durable in the store
+    ///
+    /// # Note
+    ///
+    /// A block that is rejected leaves the chain and the store unchanged. If the block is
+    /// accepted but the save fails, it stays in `blockchain` and is written by the next save
+    pub fn append_block(&mut self, block: Block) -> Result<BlockStatus, Error> {
+        let previous = self.blockchain.get_last_block().map(|last| last.hash);
+        let status = self.blockchain.submit_block(block.clone())?;
+        if status == BlockStatus::Known {
+            return Ok(status);
+        }
+        if !std::mem::replace(&mut self.synced, false) {
+            self.save()?;
+            self.synced = true;
+            return Ok(status);
+        }
+        let mut batch = WriteBatch::new();
+        match status {
+            BlockStatus::Active if Some(block.header.prev_hash) == previous => {
+                self.write_appended(&block, &mut batch)?;
+            }
+            BlockStatus::Active => {
+                // The blocks of the new branch were stored as side blocks when they arrived.
+                write_chain(&self.store, &self.blockchain, [], &mut batch)?;
+                self.write_ledger(&mut batch)?;
+            }
+            _ => batch.put(Table::Blocks, block.hash, serialize(&block)?),
+        }
+        self.store.commit(batch)?;
+        self.synced = true;
+        Ok(status)
+    }
+
+    /// Adds to `batch` the block that was just pushed onto the tip, its height entry, the new
+    /// tip and the ledger entries the block changed.
+    fn write_appended(&self, block: &Block, batch: &mut WriteBatch) -> Result<(), Error> {
+        let height = self.blockchain.chain.len() - 1;
+        batch.put(Table::Blocks, block.hash, serialize(block)?);
+        batch.put(Table::Heights, height_key(height), block.hash.as_bytes());
+        batch.put(Table::Meta, TIP_KEY, serialize(&(height, block.hash))?);
+        match self.blockchain.ledger {
+            LedgerMode::Account => {
+                let state = self.blockchain.state();
+                for address in WorldState::touched_by(&block.transactions) {
+                    let account = (state.balance_of(address), state.nonce_of(address));
+                    batch.put(Table::Accounts, address, serialize(&account)?);
+                }
+                batch.put(
+                    Table::Default,
+                    STATE_TIP_KEY,
+                    serialize(&(state.height(), state.tip()))?,
+                );
+            }
+            LedgerMode::Utxo => {
+                // In block order, so an output spent by a later transaction of the block is
+                // put and then removed.
+                for tx in &block.transactions {
+                    for input in &tx.inputs {
+                        batch.remove(Table::Utxo, input.previous_output.to_key());
+                    }
+                    let txid = tx.id();
+                    for (index, output) in tx.outputs.iter().enumerate() {
+                        let outpoint = OutPoint::new(txid.clone(), index as u32);
+                        batch.put(Table::Utxo, outpoint.to_key(), serialize(output)?);
+                    }
+                }
+                let utxos = self.blockchain.utxo_set();
+                batch.put(
+                    Table::Default,
+                    UTXO_TIP_KEY,
+                    serialize(&(utxos.height(), utxos.tip()))?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds to `batch` the ledger state of the chain's mode, removing the stored entries it
+    /// no longer holds.
+    fn write_ledger(&self, batch: &mut WriteBatch) -> Result<(), Error> {
+        let (table, tip_key, entries, tip) = match self.blockchain.ledger {
+            LedgerMode::Account => {
+                let state = self.blockchain.state();
+                let mut accounts = BTreeMap::new();
+                for (address, account) in state.accounts() {
+                    accounts.insert(address.as_bytes().to_vec(), serialize(&account)?);
+                }
+                batch.remove(Table::Default, STATE_KEY);
+                (
+                    Table::Accounts,
+                    STATE_TIP_KEY,
+                    accounts,
+                    (state.height(), state.tip()),
+                )
+            }
+            LedgerMode::Utxo => {
+                let utxos = self.blockchain.utxo_set();
+                let mut outputs = BTreeMap::new();
+                for (outpoint, output) in utxos.iter() {
+                    outputs.insert(outpoint.to_key(), serialize(output)?);
+                }
+                (
+                    Table::Utxo,
+                    UTXO_TIP_KEY,
+                    outputs,
+                    (utxos.height(), utxos.tip()),
+                )
+            }
+        };
+        for (key, value) in self.store.range(table, &[])? {
+            if entries.get(&key) != Some(&value) {
+                batch.remove(table, key);
+            }
+        }
+        for (key, value) in entries {
+            batch.put(table, key, value);
+        }
+        batch.put(Table::Default, tip_key, serialize(&tip)?);
+        Ok(())
+    }
+
+    /// Mines the next block, paying the reward and fees to `miner`, and appends it with
+    /// `append_block`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<MiningOutcome, Error>` - The mined block once it is durable in the store, or
+    ///   `MiningOutcome::Cancelled` if `cancel` stopped the search, in which case nothing is
+    ///   written
+    pub fn mine_and_append(
+        &mut self,
+        miner: &str,
+        transactions: Vec<Transaction>,
+        cancel: &CancelToken,
+        progress: impl FnMut(&MiningStats),
+    ) -> Result<MiningOutcome, Error> {
+        let block = self.blockchain.prepare_block(miner, transactions)?;
+        let outcome = self.blockchain.seal(&block, cancel, progress)?;
+        if let MiningOutcome::Mined { block, .. } = &outcome {
+            self.append_block(block.clone())?;
+        }
+        Ok(outcome)
+    }
+
+    /// Saves the current blockchain state to the store
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Ok(()) if save is successful, Error otherwise
+    ///
+    /// # Note
+    ///
+    /// Only the blocks that are not stored yet and the heights whose block changed are
+    /// written; blocks that dropped off the active chain and are not kept as side blocks are
+    /// removed. Every side block is looked up in the store and the ledger tables are compared
+    /// with the whole ledger state, so a save costs more as the chain grows; `append_block`
+    /// only calls it after a failed save. The new blocks, the index, the tip and the ledger
+    /// state are committed to the store in a single batch, so a crash leaves the store at
+    /// either the previous save or this one. An error is returned if the store cannot make
+    /// the batch durable
+    pub fn save(&self) -> Result<(), Error> {
+        let mut batch = WriteBatch::new();
+        write_chain(
+            &self.store,
+            &self.blockchain,
+            self.blockchain.side_blocks(),
+            &mut batch,
+        )?;
+        self.write_ledger(&mut batch)?;
+        self.store.commit(batch)
+    }
+}
+
+/// Adds to `batch` the blocks of the active chain of `blockchain` and the `side_blocks` that
+/// `store` does not hold yet, the height index entries that changed, the tip, the chain
+/// parameters and the schema version, and removes the single-key chain of older databases.
+pub(super) fn write_chain<'a>(
+    store: &(impl ChainStore + ?Sized),
+    blockchain: &'a Blockchain,
+    side_blocks: impl IntoIterator<Item = &'a Block>,
+    batch: &mut WriteBatch,
+) -> Result<(), Error> {
+    let chain = &blockchain.chain;
+    let Some(last) = chain.last() else {
+        return Err(Error::MissingChain);
+    };
+
+    // Walk down from the tip to the first height the index already agrees with.
+    let mut connected = Vec::new();
+    let mut replaced = Vec::new();
+    for (height, block) in chain.iter().enumerate().rev() {
+        match store.get(Table::Heights, &height_key(height))? {
+            Some(hash) if hash == block.hash.as_bytes() => break,
+            Some(hash) => replaced.push(hash),
+            None => {}
+        }
+        connected.push(height);
+    }
+    for (key, hash) in store.range(Table::Heights, &height_key(chain.len()))? {
+        batch.remove(Table::Heights, key);
+        replaced.push(hash);
+    }
+    for hash in replaced {
+        let known = Hash256::from_slice(&hash).is_ok_and(|hash| blockchain.contains_block(&hash));
+        if !known {
+            batch.remove(Table::Blocks, hash);
+        }
+    }
+    let new_blocks = connected
+        .iter()
+        .map(|&height| &chain[height])
+        .chain(side_blocks);
+    for block in new_blocks {
+        if !store.contains(Table::Blocks, block.hash.as_bytes())? {
+            batch.put(Table::Blocks, block.hash, serialize(block)?);
+        }
+    }
+    for &height in &connected {
+        batch.put(
+            Table::Heights,
+            height_key(height),
+            chain[height].hash.as_bytes(),
+        );
+    }
+
+    let params = ChainParams::of(blockchain);
+    batch.put(
+        Table::Meta,
+        TIP_KEY,
+        serialize(&(chain.len() - 1, last.hash))?,
+    );
+    batch.put(Table::Meta, PARAMS_KEY, serialize(&params)?);
+    batch.put(Table::Meta, SCHEMA_VERSION_KEY, serialize(&SCHEMA_VERSION)?);
+    batch.remove(Table::Default, BLOCKCHAIN_KEY);
+    Ok(())
+}
+
+/// The key of `height` in the height index; big-endian so that heights sort numerically.
+fn height_key(height: usize) -> [u8; 8] {
+    (height as u64).to_be_bytes()
+}
+
+fn read_block_at(store: &impl ChainStore, height: usize) -> Result<Block, Error> {
+    let hash = store
+        .get(Table::Heights, &height_key(height))?
+        .ok_or(Error::MissingBlock { height })?;
+    let data = store
+        .get(Table::Blocks, &hash)?
+        .ok_or(Error::MissingBlock { height })?;
+    decode(store, Table::Blocks, &hash, data)
+}
+
+/// Deserializes `data`, stored under `key` in `table`. Undecodable bytes are moved from
+/// `table` to a quarantine key of `Table::Default`.
+pub(super) fn decode<T: DeserializeOwned>(
+    store: &(impl ChainStore + ?Sized),
+    table: Table,
+    key: &[u8],
+    data: Vec<u8>,
+) -> Result<T, Error> {
+    deserialize(&data).or_else(|source| {
+        let quarantine_key = timestamped_key(QUARANTINE_PREFIX);
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Default, &quarantine_key, data);
+        batch.remove(table, key);
+        store.commit(batch)?;
+        Err(Error::Undecodable {
+            quarantine_key,
+            source,
+        })
+    })
+}
+
+/// A key made of `prefix` and the current time in nanoseconds.
+pub(super) fn timestamped_key(prefix: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{}{}", prefix, nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::utxo::TxOutput;
+    use crate::storage::flat_file::{FlatFileStore, HEADER_LEN};
+    use crate::storage::memory::MemoryStore;
+    use crate::storage::store::{Entry, Write};
+    use crate::utils::crypto::Keypair;
+    use std::cell::{Cell, RefCell};
+    use std::io;
+    use tempfile::tempdir;
+
+    const MINER: &str = "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450";
+
+    /// Opens a manager over `db`. Tests share one sled handle instead of reopening a path,
+    /// since sled releases its file lock from background threads after the last handle drops.
+    fn open_db(db: &sled::Db, options: OpenOptions) -> Result<BlockchainManager, Error> {
+        BlockchainManager::with_store(SledStore::from_db(db.clone())?, options)
+    }
+
+    fn temporary_db() -> sled::Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    /// Mines a block holding `transactions` and appends it through the manager.
+    fn mine_block(
+        manager: &mut BlockchainManager<impl ChainStore>,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        match manager
+            .mine_and_append(MINER, transactions, &CancelToken::new(), |_| {})
+            .unwrap()
+        {
+            MiningOutcome::Mined { block, .. } => block,
+            MiningOutcome::Cancelled { .. } => panic!("mining was cancelled"),
+        }
+    }
+
+    fn memo(memo: &str) -> Vec<Transaction> {
+        vec![Transaction::memo(memo).unwrap()]
+    }
+
+    #[test]
+    fn test_blockchain_manager_new() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().to_str().unwrap();
+
+        let manager = BlockchainManager::new(db_path).unwrap();
+        assert_eq!(manager.get_blockchain().chain.len(), 1); // Genesis block
+    }
+
+    #[test]
+    fn test_blockchain_manager_save_and_load() {
+        let db = temporary_db();
+
+        // Create and save blockchain
+        {
+            let mut manager1 = open_db(&db, OpenOptions::default()).unwrap();
+            mine_block(&mut manager1, memo("Test data"));
+        }
+
+        // Load and verify
+        let manager2 = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(manager2.get_blockchain().chain.len(), 2);
+    }
+
+    #[test]
+    fn test_blockchain_manager_invalid_path() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let db_path = file.path().join("db");
+        let result = BlockchainManager::new(db_path.to_str().unwrap());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_blockchain_manager_drop() {
+        let db = temporary_db();
+
+        {
+            let mut manager = open_db(&db, OpenOptions::default()).unwrap();
+            mine_block(&mut manager, memo("Drop test"));
+        } // manager gets dropped here, without a save
+
+        let new_manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(new_manager.get_blockchain().chain.len(), 2);
+    }
+
+    fn write_tampered_chain(db: &sled::Db) {
+        let mut chain = Blockchain::new(DEFAULT_DIFFICULTY).unwrap();
+        chain
+            .add_block(MINER, vec![Transaction::memo("block 1").unwrap()])
+            .unwrap();
+        chain
+            .add_block(MINER, vec![Transaction::memo("block 2").unwrap()])
+            .unwrap();
+        chain.chain[2].transactions[0].memo = "forged".to_string();
+        db.insert(BLOCKCHAIN_KEY, serialize(&chain).unwrap())
+            .unwrap();
+        db.flush().unwrap();
+    }
+
+    #[test]
+    fn test_blockchain_manager_missing() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().to_str().unwrap();
+
+        let options = OpenOptions {
+            create_if_missing: false,
+            ..OpenOptions::default()
+        };
+        let result = BlockchainManager::open(db_path, options);
+        assert!(matches!(result, Err(Error::MissingChain)));
+    }
+
+    #[test]
+    fn test_blockchain_manager_quarantines_undecodable_data() {
+        let db = temporary_db();
+        db.insert(BLOCKCHAIN_KEY, vec![0xFF; 3]).unwrap();
+
+        let key = match open_db(&db, OpenOptions::default()) {
+            Err(Error::Undecodable { quarantine_key, .. }) => quarantine_key,
+            other => panic!("unexpected result: {:?}", other.err()),
+        };
+
+        let store = SledStore::from_db(db).unwrap();
+        let stored = |key: &str| store.get(Table::Default, key.as_bytes()).unwrap();
+        assert_eq!(stored(BLOCKCHAIN_KEY), None);
+        assert_eq!(stored(&key), Some(vec![0xFF; 3]));
+    }
+
+    #[test]
+    fn test_blockchain_manager_refuses_corrupt_chain() {
+        let db = temporary_db();
+        write_tampered_chain(&db);
+
+        match open_db(&db, OpenOptions::default()) {
+            Err(Error::Validation(err)) => assert_eq!(err.height, 2),
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_blockchain_manager_repair_truncates_to_last_valid_height() {
+        let db = temporary_db();
+        write_tampered_chain(&db);
+
+        let options = OpenOptions {
+            repair: true,
+            ..OpenOptions::default()
+        };
+        {
+            let manager = open_db(&db, options).unwrap();
+            assert_eq!(manager.get_blockchain().chain.len(), 2);
+        }
+
+        let manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(manager.get_blockchain().chain.len(), 2);
+        let quarantined = manager.store().db().scan_prefix(QUARANTINE_PREFIX);
+        assert_eq!(quarantined.count(), 1);
+    }
+
+    #[test]
+    fn test_blockchain_manager_persists_state() {
+        let db = temporary_db();
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        {
+            let chain = Blockchain::with_allocations(DEFAULT_DIFFICULTY, &[(alice.address(), 100)])
+                .unwrap();
+            let store = SledStore::from_db(db.clone()).unwrap();
+            let mut manager = BlockchainManager::start(store, chain).unwrap();
+            let mut tx = Transaction::new(&alice.address(), &bob, 25, 0).unwrap();
+            tx.sign(&alice);
+            mine_block(&mut manager, vec![tx]);
+        }
+
+        let store = SledStore::from_db(db.clone()).unwrap();
+        let account = store.get(Table::Accounts, bob.as_bytes()).unwrap();
+        assert_eq!(
+            deserialize::<(u64, u64)>(&account.unwrap()).unwrap(),
+            (25, 0)
+        );
+        let tip = store.get(Table::Default, STATE_TIP_KEY.as_bytes()).unwrap();
+        let (height, _): (usize, Hash256) = deserialize(&tip.unwrap()).unwrap();
+        assert_eq!(height, 2);
+
+        let manager = open_db(&db, OpenOptions::default()).unwrap();
+        let replayed = WorldState::from_blocks(&manager.blockchain().chain).unwrap();
+        assert_eq!(manager.blockchain().state(), &replayed);
+        assert_eq!(manager.blockchain().balance_of(&bob), 25);
+        assert_eq!(manager.blockchain().nonce_of(&alice.address()), 1);
+    }
+
+    #[test]
+    fn test_blockchain_manager_reads_single_key_state() {
+        let db = temporary_db();
+        let alice = Keypair::generate();
+        let chain =
+            Blockchain::with_allocations(DEFAULT_DIFFICULTY, &[(alice.address(), 100)]).unwrap();
+        let state = chain.state().clone();
+        BlockchainManager::start(SledStore::from_db(db.clone()).unwrap(), chain).unwrap();
+        let store = SledStore::from_db(db.clone()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.remove(Table::Accounts, alice.address());
+        batch.remove(Table::Default, STATE_TIP_KEY);
+        batch.put(Table::Default, STATE_KEY, serialize(&state).unwrap());
+        store.commit(batch).unwrap();
+
+        let mut manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().state(), &state);
+        mine_block(&mut manager, memo("block 1"));
+        assert_eq!(
+            store.get(Table::Default, STATE_KEY.as_bytes()).unwrap(),
+            None
+        );
+        assert!(
+            store
+                .contains(Table::Accounts, alice.address().as_bytes())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_blockchain_manager_rebuilds_stale_state() {
+        let db = temporary_db();
+        let alice = Keypair::generate();
+        let chain =
+            Blockchain::with_allocations(DEFAULT_DIFFICULTY, &[(alice.address(), 100)]).unwrap();
+        BlockchainManager::start(SledStore::from_db(db.clone()).unwrap(), chain).unwrap();
+        db.insert(STATE_TIP_KEY, serialize(&(0usize, Hash256::ZERO)).unwrap())
+            .unwrap();
+
+        let manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().balance_of(&alice.address()), 100);
+    }
+
+    fn utxo_chain(owner: &Keypair, recipient: &str) -> Blockchain {
+        let mut chain = Blockchain::with_ledger(
+            DEFAULT_DIFFICULTY,
+            LedgerMode::Utxo,
+            &[(owner.address(), 40)],
+        )
+        .unwrap();
+        let address = owner.address();
+        let (coin, _) = chain.utxo_set().unspent_for(&address).next().unwrap();
+        let output = TxOutput {
+            value: 15,
+            locking_key: recipient.to_string(),
+        };
+        let mut tx = Transaction::utxo(vec![coin.clone()], vec![output]).unwrap();
+        tx.sign_input(0, owner);
+        chain.add_block(MINER, vec![tx]).unwrap();
+        chain
+    }
+
+    #[test]
+    fn test_blockchain_manager_persists_utxo_tree() {
+        let db = temporary_db();
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let store = SledStore::from_db(db.clone()).unwrap();
+        BlockchainManager::start(store.clone(), utxo_chain(&alice, &bob)).unwrap();
+
+        let outputs = store.range(Table::Utxo, &[]).unwrap();
+        assert_eq!(outputs.len(), 2); // Bob's output and the coinbase
+        assert!(OutPoint::from_key(&outputs[0].0).is_some());
+
+        let manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().ledger, LedgerMode::Utxo);
+        assert_eq!(manager.blockchain().balance_of(&bob), 15);
+        assert_eq!(manager.blockchain().balance_of(&alice.address()), 0);
+        assert_eq!(manager.blockchain().balance_of(MINER), 50);
+    }
+
+    #[test]
+    fn test_blockchain_manager_rebuilds_utxo_tree() {
+        let db = temporary_db();
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let store = SledStore::from_db(db.clone()).unwrap();
+        BlockchainManager::start(store.clone(), utxo_chain(&alice, &bob)).unwrap();
+        let mut batch = WriteBatch::new();
+        for (key, _) in store.range(Table::Utxo, &[]).unwrap() {
+            batch.remove(Table::Utxo, key);
+        }
+        store.commit(batch).unwrap();
+
+        let mut manager = open_db(&db, OpenOptions::default()).unwrap();
+        assert!(manager.blockchain().utxo_set().is_empty());
+        manager.rebuild_ledger().unwrap();
+        assert_eq!(manager.blockchain().balance_of(&bob), 15);
+        assert_eq!(manager.store().range(Table::Utxo, &[]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_blockchain_manager_stores_blocks_individually() {
+        let mut manager =
+            BlockchainManager::with_store(MemoryStore::new(), OpenOptions::default()).unwrap();
+        for text in ["block 1", "block 2"] {
+            mine_block(&mut manager, memo(text));
+        }
+
+        let store = manager.store();
+        assert_eq!(store.range(Table::Blocks, &[]).unwrap().len(), 3);
+        assert_eq!(store.range(Table::Heights, &[]).unwrap().len(), 3);
+        let hash = manager.blockchain().chain[1].hash;
+        assert_eq!(
+            manager.block_at(1).unwrap().map(|block| block.hash),
+            Some(hash)
+        );
+        let block = manager.block_by_hash(&hash).unwrap().unwrap();
+        assert_eq!(block.header.prev_hash, manager.blockchain().chain[0].hash);
+        assert!(manager.block_at(3).unwrap().is_none());
+        assert!(manager.block_by_hash(&Hash256::ZERO).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_blockchain_manager_save_appends_only_new_blocks() {
+        let store = MemoryStore::new();
+        let open = |options: OpenOptions| BlockchainManager::with_store(store.clone(), options);
+        {
+            let mut manager = open(OpenOptions::default()).unwrap();
+            let hash = mine_block(&mut manager, memo("block 1")).hash;
+
+            // Saved blocks are not written again, so a torn value stays torn.
+            let mut batch = WriteBatch::new();
+            batch.put(Table::Blocks, hash, vec![0xFF; 3]);
+            store.commit(batch).unwrap();
+            mine_block(&mut manager, memo("block 2"));
+            let stored = store.get(Table::Blocks, hash.as_bytes()).unwrap();
+            assert_eq!(stored, Some(vec![0xFF; 3]));
+            assert!(manager.block_at(2).unwrap().is_some());
+        }
+
+        let key = match open(OpenOptions::default()) {
+            Err(Error::Undecodable { quarantine_key, .. }) => quarantine_key,
+            other => panic!("unexpected result: {:?}", other.err()),
+        };
+        let quarantined = store.get(Table::Default, key.as_bytes()).unwrap();
+        assert_eq!(quarantined, Some(vec![0xFF; 3]));
+        assert!(matches!(
+            open(OpenOptions::default()),
+            Err(Error::MissingBlock { height: 1 })
+        ));
+
+        let manager = open(OpenOptions {
+            repair: true,
+            ..OpenOptions::default()
+        })
+        .unwrap();
+        assert_eq!(manager.blockchain().chain.len(), 1);
+        assert!(manager.block_at(1).unwrap().is_none());
+        assert_eq!(store.range(Table::Blocks, &[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_blockchain_manager_repair_quarantines_dropped_blocks() {
+        for corrupt in [2, 0] {
+            let store = MemoryStore::new();
+            let hashes: Vec<_> = {
+                let mut manager =
+                    BlockchainManager::with_store(store.clone(), OpenOptions::default()).unwrap();
+                for i in 1..5 {
+                    mine_block(&mut manager, memo(&format!("block {}", i)));
+                }
+                manager
+                    .blockchain()
+                    .iter()
+                    .map(|block| block.hash)
+                    .collect()
+            };
+            let mut batch = WriteBatch::new();
+            batch.put(Table::Blocks, hashes[corrupt], vec![0xFF; 3]);
+            store.commit(batch).unwrap();
+
+            let options = OpenOptions {
+                repair: true,
+                ..OpenOptions::default()
+            };
+            let manager = BlockchainManager::with_store(store.clone(), options).unwrap();
+            assert_eq!(manager.blockchain().chain.len(), corrupt.max(1));
+
+            let quarantined: Vec<_> = store
+                .range(Table::Default, QUARANTINE_PREFIX.as_bytes())
+                .unwrap()
+                .into_iter()
+                .filter(|(key, _)| key.starts_with(QUARANTINE_PREFIX.as_bytes()))
+                .collect();
+            assert!(quarantined.iter().any(|(_, data)| *data == vec![0xFF; 3]));
+            let backed_up: Vec<_> = quarantined
+                .iter()
+                .filter_map(|(_, data)| deserialize::<Vec<Block>>(data).ok())
+                .flatten()
+                .map(|block| block.hash)
+                .collect();
+            assert_eq!(backed_up, hashes[corrupt + 1..]);
+        }
+    }
+
+    #[test]
+    fn test_blockchain_manager_persists_side_blocks() {
+        let store = MemoryStore::new();
+        let side = {
+            let mut manager =
+                BlockchainManager::with_store(store.clone(), OpenOptions::default()).unwrap();
+            let mut fork = manager.get_blockchain();
+            fork.add_block(MINER, vec![Transaction::memo("fork").unwrap()])
+                .unwrap();
+            mine_block(&mut manager, memo("main"));
+            let side = fork.chain[1].clone();
+            let status = manager.append_block(side.clone()).unwrap();
+            assert_eq!(status, BlockStatus::SideChain);
+            side
+        };
+
+        let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().chain.len(), 2);
+        assert!(manager.blockchain().contains_block(&side.hash));
+        let stored = manager.block_by_hash(&side.hash).unwrap();
+        assert_eq!(stored.map(|block| block.hash), Some(side.hash));
+    }
+
+    #[test]
+    fn test_blockchain_manager_appends_durably() {
+        let store = MemoryStore::new();
+        let mut manager =
+            BlockchainManager::with_store(store.clone(), OpenOptions::default()).unwrap();
+        let block = mine_block(&mut manager, memo("kept"));
+        let mut fork = Blockchain::from_parts(
+            DEFAULT_DIFFICULTY,
+            LedgerMode::Account,
+            Consensus::default(),
+            manager.blockchain().chain[..1].to_vec(),
+            Vec::new(),
+        );
+        fork.rebuild_state().unwrap();
+        fork.add_block(MINER, vec![Transaction::memo("fork").unwrap()])
+            .unwrap();
+        let side = fork.chain[1].clone();
+        assert_eq!(
+            manager.append_block(side.clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        let mut forged = side.clone();
+        forged.transactions[0].memo = "forged".to_string();
+        forged.hash = Hash256::from_slice(&[1; 32]).unwrap();
+        assert!(manager.append_block(forged).is_err());
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let outcome = manager
+            .mine_and_append(MINER, Vec::new(), &cancel, |_| {})
+            .unwrap();
+        assert!(matches!(outcome, MiningOutcome::Cancelled { .. }));
+        // Killed without running `Drop`: everything appended is already in the store.
+        std::mem::forget(manager);
+
+        let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().chain.len(), 2);
+        assert_eq!(manager.blockchain().chain[1].hash, block.hash);
+        assert_eq!(manager.blockchain().side_blocks().count(), 1);
+        assert!(manager.blockchain().contains_block(&side.hash));
+    }
+
+    #[test]
+    fn test_blockchain_manager_with_flat_file_store() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        {
+            let store = FlatFileStore::open(&path).unwrap();
+            let mut manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+            mine_block(&mut manager, memo("block 1"));
+        }
+
+        let store = FlatFileStore::open(&path).unwrap();
+        let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(manager.blockchain().chain.len(), 2);
+        assert!(manager.block_at(1).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_blockchain_manager_migrates_single_key_layout() {
+        let db = temporary_db();
+        let mut chain = Blockchain::new(DEFAULT_DIFFICULTY).unwrap();
+        chain
+            .add_block(MINER, vec![Transaction::memo("block 1").unwrap()])
+            .unwrap();
+        db.insert(BLOCKCHAIN_KEY, serialize(&chain).unwrap())
+            .unwrap();
+
+        let manager = open_db(&db, OpenOptions::default()).unwrap();
+        let hashes = |chain: &Blockchain| chain.iter().map(|block| block.hash).collect::<Vec<_>>();
+        assert_eq!(hashes(manager.blockchain()), hashes(&chain));
+        let legacy = manager
+            .store()
+            .get(Table::Default, BLOCKCHAIN_KEY.as_bytes());
+        assert_eq!(legacy.unwrap(), None);
+        let block = manager.block_at(1).unwrap().unwrap();
+        assert_eq!(block.hash, chain.chain[1].hash);
+    }
+
+    /// A `MemoryStore` that keeps a copy of every batch it commits.
+    #[derive(Default)]
+    struct RecordingStore {
+        inner: MemoryStore,
+        batches: RefCell<Vec<WriteBatch>>,
+    }
+
+    impl ChainStore for RecordingStore {
+        fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            self.inner.get(table, key)
+        }
+
+        fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error> {
+            self.inner.range(table, start)
+        }
+
+        fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+            self.batches.borrow_mut().push(batch.clone());
+            self.inner.commit(batch)
+        }
+    }
+
+    /// The tables and keys written by the last batch committed to `store`.
+    fn last_writes(store: &RecordingStore) -> Vec<(Table, Vec<u8>)> {
+        let batches = store.batches.borrow();
+        let writes = batches.last().unwrap().writes();
+        writes
+            .iter()
+            .map(|write| match write {
+                Write::Put { table, key, .. } | Write::Remove { table, key } => {
+                    (*table, key.clone())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_blockchain_manager_appends_only_the_delta() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate().address();
+        let others: Vec<_> = (0..20)
+            .map(|_| (Keypair::generate().address(), 5))
+            .collect();
+        let mut allocations = vec![(alice.address(), 100)];
+        allocations.extend(others);
+        let chain = Blockchain::with_allocations(DEFAULT_DIFFICULTY, &allocations).unwrap();
+        let mut manager = BlockchainManager::start(RecordingStore::default(), chain).unwrap();
+
+        let mut tx = Transaction::new(&alice.address(), &bob, 25, 0).unwrap();
+        tx.sign(&alice);
+        let block = mine_block(&mut manager, vec![tx]);
+        let mut writes = last_writes(manager.store());
+        writes.sort();
+        let mut expected = vec![
+            (Table::Default, STATE_TIP_KEY.as_bytes().to_vec()),
+            (Table::Blocks, block.hash.as_bytes().to_vec()),
+            (Table::Heights, height_key(1).to_vec()),
+            (Table::Meta, TIP_KEY.as_bytes().to_vec()),
+            (Table::Accounts, alice.address().into_bytes()),
+            (Table::Accounts, bob.clone().into_bytes()),
+            (Table::Accounts, MINER.as_bytes().to_vec()),
+        ];
+        expected.sort();
+        assert_eq!(writes, expected);
+
+        // A side block is written on its own.
+        let mut fork = manager.get_blockchain();
+        fork.chain.truncate(1);
+        fork.rebuild_state().unwrap();
+        fork.add_block(MINER, memo("fork")).unwrap();
+        let side = fork.chain[1].clone();
+        assert_eq!(
+            manager.append_block(side.clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        let side_write = (Table::Blocks, side.hash.as_bytes().to_vec());
+        assert_eq!(last_writes(manager.store()), vec![side_write.clone()]);
+
+        // Extending the side branch past the tip reorganizes onto it.
+        fork.add_block(MINER, memo("fork 2")).unwrap();
+        let status = manager.append_block(fork.chain[2].clone()).unwrap();
+        assert_eq!(status, BlockStatus::Active);
+        assert!(!last_writes(manager.store()).contains(&side_write));
+
+        let store = MemoryStore::clone(&manager.store().inner);
+        let reopened = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(reopened.blockchain().state(), manager.blockchain().state());
+        assert_eq!(reopened.blockchain().balance_of(&bob), 0);
+        assert_eq!(reopened.blockchain().side_blocks().count(), 1);
+    }
+
+    #[test]
+    fn test_blockchain_manager_appends_utxo_delta() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let chain = utxo_chain(&alice, &bob.address());
+        let mut manager = BlockchainManager::start(RecordingStore::default(), chain).unwrap();
+        let bob_address = bob.address();
+        let (coin, _) = manager
+            .blockchain()
+            .utxo_set()
+            .unspent_for(&bob_address)
+            .next()
+            .unwrap();
+        let coin = coin.clone();
+        let output = TxOutput {
+            value: 10,
+            locking_key: alice.address(),
+        };
+        let mut tx = Transaction::utxo(vec![coin.clone()], vec![output]).unwrap();
+        tx.sign_input(0, &bob);
+        let spent = (Table::Utxo, coin.to_key());
+        mine_block(&mut manager, vec![tx]);
+
+        let writes = last_writes(manager.store());
+        assert!(writes.contains(&spent));
+        // The spent output, the new output and the coinbase, next to the block, its height,
+        // the tip and the tip of the set.
+        assert_eq!(writes.len(), 7);
+        let store = MemoryStore::clone(&manager.store().inner);
+        let reopened = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        assert_eq!(
+            reopened.blockchain().utxo_set(),
+            manager.blockchain().utxo_set()
+        );
+        assert_eq!(reopened.blockchain().balance_of(&alice.address()), 10);
+    }
+
+    /// A store that takes `commits` more batches and then fails every later one without
+    /// writing it, as if the process had been killed there.
+    struct CrashingStore {
+        inner: MemoryStore,
+        commits: Cell<usize>,
+    }
+
+    impl ChainStore for CrashingStore {
+        fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            self.inner.get(table, key)
+        }
+
+        fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error> {
+            self.inner.range(table, start)
+        }
+
+        fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+            match self.commits.get() {
+                0 => Err(Error::Io(io::Error::other("process killed"))),
+                left => {
+                    self.commits.set(left - 1);
+                    self.inner.commit(batch)
+                }
+            }
+        }
+    }
+
+    /// Saves a chain funding `alice`, then mines and appends three of her transfers, stopping
+    /// at the first save that fails. Returns the chain length of every save that went through.
+    fn save_until_crash(store: impl ChainStore, alice: &Keypair) -> Vec<usize> {
+        let chain =
+            Blockchain::with_allocations(DEFAULT_DIFFICULTY, &[(alice.address(), 100)]).unwrap();
+        let Ok(mut manager) = BlockchainManager::start(store, chain) else {
+            return Vec::new();
+        };
+        let mut saved = vec![manager.blockchain().chain.len()];
+        for nonce in 0..3 {
+            let mut tx = Transaction::new(&alice.address(), MINER, 10, nonce).unwrap();
+            tx.sign(alice);
+            let appended = manager.mine_and_append(MINER, vec![tx], &CancelToken::new(), |_| {});
+            if appended.is_err() {
+                break;
+            }
+            saved.push(manager.blockchain().chain.len());
+        }
+        saved
+    }
+
+    /// Checks that a reopened chain agrees with its ledger state and with the stored index,
+    /// and returns its length.
+    fn consistent_tip(manager: &BlockchainManager<impl ChainStore>) -> usize {
+        let chain = &manager.blockchain().chain;
+        let replayed = WorldState::from_blocks(chain).unwrap();
+        assert_eq!(manager.blockchain().state(), &replayed);
+        if let Some(stored) = manager.block_at(chain.len() - 1).unwrap() {
+            assert_eq!(stored.hash, chain.last().unwrap().hash);
+        }
+        assert!(manager.block_at(chain.len()).unwrap().is_none());
+        chain.len()
+    }
+
+    #[test]
+    fn test_blockchain_manager_recovers_from_crash_between_commits() {
+        let alice = Keypair::generate();
+        for commits in 0..6 {
+            let store = MemoryStore::new();
+            let crashing = CrashingStore {
+                inner: store.clone(),
+                commits: Cell::new(commits),
+            };
+            let saved = save_until_crash(crashing, &alice);
+            assert_eq!(saved.len(), commits.min(4));
+
+            let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+            let len = consistent_tip(&manager);
+            // With nothing saved a new chain is started.
+            assert_eq!(len, saved.last().copied().unwrap_or(1));
+            if !saved.is_empty() {
+                let spent = 10 * (len as u64 - 1);
+                assert_eq!(
+                    manager.blockchain().balance_of(&alice.address()),
+                    100 - spent
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_blockchain_manager_recovers_from_torn_flat_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        let alice = Keypair::generate();
+        let saved = save_until_crash(FlatFileStore::open(&path).unwrap(), &alice);
+        let log = std::fs::read(&path).unwrap();
+
+        // Cut the log inside and at the end of every record, as if the process died in the
+        // middle of a write.
+        let mut cuts = Vec::new();
+        let mut start = 0;
+        while start < log.len() {
+            let payload = u32::from_le_bytes(log[start..start + 4].try_into().unwrap());
+            let end = start + HEADER_LEN as usize + payload as usize;
+            cuts.extend([
+                start + 1,
+                start + 4,
+                start + HEADER_LEN as usize + 1,
+                (start + end) / 2,
+                end - 1,
+                end,
+            ]);
+            start = end;
+        }
+        let torn = temp_dir.path().join("torn.log");
+        let mut previous = 1;
+        for cut in cuts {
+            std::fs::write(&torn, &log[..cut]).unwrap();
+            let store = FlatFileStore::open(&torn).unwrap();
+            let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+            let len = consistent_tip(&manager);
+            assert!(saved.contains(&len) && len >= previous);
+            previous = len;
+        }
+        assert_eq!(Some(&previous), saved.last());
+    }
+}
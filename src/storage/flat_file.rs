@@ -0,0 +1,408 @@
+use super::store::{ChainStore, Entry, Table, Write, WriteBatch};
+use crate::error::Error;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Length of a record header: the payload length and the checksums of the length and of the
+/// payload.
+pub(crate) const HEADER_LEN: u64 = 12;
+const PUT: u8 = 0;
+const REMOVE: u8 = 1;
+
+/// Where the current value of a key starts in the file, and its length.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: u32,
+}
+
+#[derive(Debug)]
+struct Log {
+    file: File,
+    len: u64,
+    index: BTreeMap<(Table, Vec<u8>), Location>,
+}
+
+/// A `ChainStore` that appends every batch to a single file.
+///
+/// Each committed batch is one record: its payload length as a little-endian `u32`, the first
+/// four bytes of the SHA-256 of those length bytes, the first four bytes of the SHA-256 of the
+/// payload, and the payload, a sequence of writes. A put is
+/// `0`, the table, the key length as a `u32`, the key, the value length as a `u32` and the
+/// value; a remove is `1`, the table and the key. The file is synced after every record.
+///
+/// The index from each key to the offset of its current value is kept in memory and rebuilt
+/// by replaying the records when the store is opened. A last record cut short by a crash is
+/// truncated away: its header is incomplete, or the header checks out and the file ends inside
+/// its payload. Any other bad record makes `open` fail instead of dropping the records after
+/// it. Replaced and removed values stay in the file.
+#[derive(Debug)]
+pub struct FlatFileStore {
+    log: Mutex<Log>,
+}
+
+impl FlatFileStore {
+    /// Opens the log at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut index = BTreeMap::new();
+        let len = replay(&mut file, &mut index)?;
+        if file.metadata()?.len() > len {
+            file.set_len(len)?;
+            file.sync_all()?;
+        }
+        Ok(Self {
+            log: Mutex::new(Log { file, len, index }),
+        })
+    }
+
+    fn log(&self) -> MutexGuard<'_, Log> {
+        self.log
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Log {
+    fn read(&mut self, location: Location) -> io::Result<Vec<u8>> {
+        let mut value = vec![0; location.len as usize];
+        self.file.seek(SeekFrom::Start(location.offset))?;
+        self.file.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    /// Appends `record` and syncs it, cutting the file back to its previous length if that
+    /// fails so that later records stay reachable.
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        let written = self
+            .file
+            .write_all(record)
+            .and_then(|()| self.file.sync_data());
+        if let Err(err) = written {
+            let _ = self.file.set_len(self.len);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+impl ChainStore for FlatFileStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut log = self.log();
+        match log.index.get(&(table, key.to_vec())).copied() {
+            Some(location) => Ok(Some(log.read(location)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error> {
+        let mut log = self.log();
+        let locations: Vec<_> = log
+            .index
+            .range((table, start.to_vec())..)
+            .take_while(|((entry_table, _), _)| *entry_table == table)
+            .map(|((_, key), location)| (key.clone(), *location))
+            .collect();
+        let mut entries = Vec::with_capacity(locations.len());
+        for (key, location) in locations {
+            entries.push((key, log.read(location)?));
+        }
+        Ok(entries)
+    }
+
+    fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let payload = encode(&batch);
+        let mut record = Vec::with_capacity(HEADER_LEN as usize + payload.len());
+        let len = (payload.len() as u32).to_le_bytes();
+        record.extend(len);
+        record.extend(checksum(&len));
+        record.extend(checksum(&payload));
+        record.extend(&payload);
+
+        let mut log = self.log();
+        log.append(&record)?;
+        let start = log.len + HEADER_LEN;
+        log.len += record.len() as u64;
+        apply(&payload, start, &mut log.index);
+        Ok(())
+    }
+
+    fn contains(&self, table: Table, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.log().index.contains_key(&(table, key.to_vec())))
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(payload);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn encode(batch: &WriteBatch) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for write in batch.writes() {
+        match write {
+            Write::Put { table, key, value } => {
+                payload.extend([PUT, *table as u8]);
+                payload.extend((key.len() as u32).to_le_bytes());
+                payload.extend(key);
+                payload.extend((value.len() as u32).to_le_bytes());
+                payload.extend(value);
+            }
+            Write::Remove { table, key } => {
+                payload.extend([REMOVE, *table as u8]);
+                payload.extend((key.len() as u32).to_le_bytes());
+                payload.extend(key);
+            }
+        }
+    }
+    payload
+}
+
+/// A write decoded from a payload; the value is given by its position in the payload.
+enum Op<'a> {
+    Put {
+        table: Table,
+        key: &'a [u8],
+        value: (usize, u32),
+    },
+    Remove {
+        table: Table,
+        key: &'a [u8],
+    },
+}
+
+/// Reads the fields of a payload in order.
+struct Cursor<'a> {
+    payload: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.payload.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn take_len(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+/// Splits a payload into its writes, or returns `None` if it is malformed.
+fn parse(payload: &[u8]) -> Option<Vec<Op<'_>>> {
+    let mut entries = Vec::new();
+    let mut cursor = Cursor { payload, pos: 0 };
+    while cursor.pos < payload.len() {
+        let op = cursor.take(2)?;
+        let table = *Table::ALL.get(op[1] as usize)?;
+        let key_len = cursor.take_len()?;
+        let key = cursor.take(key_len as usize)?;
+        match op[0] {
+            PUT => {
+                let value_len = cursor.take_len()?;
+                let start = cursor.pos;
+                cursor.take(value_len as usize)?;
+                entries.push(Op::Put {
+                    table,
+                    key,
+                    value: (start, value_len),
+                });
+            }
+            REMOVE => entries.push(Op::Remove { table, key }),
+            _ => return None,
+        }
+    }
+    Some(entries)
+}
+
+/// Updates `index` with the writes of a payload stored at `start`.
+fn apply(payload: &[u8], start: u64, index: &mut BTreeMap<(Table, Vec<u8>), Location>) {
+    for entry in parse(payload).unwrap_or_default() {
+        match entry {
+            Op::Put { table, key, value } => {
+                let location = Location {
+                    offset: start + value.0 as u64,
+                    len: value.1,
+                };
+                index.insert((table, key.to_vec()), location);
+            }
+            Op::Remove { table, key } => {
+                index.remove(&(table, key.to_vec()));
+            }
+        }
+    }
+}
+
+/// Replays every intact record of `file` into `index` and returns the length they cover.
+///
+/// Only the last record may be cut short, as a crash can leave it half-written. Since the
+/// length has its own checksum, a record whose payload runs past the end of the file is known
+/// to be torn rather than to have a corrupt length; any record that fails a checksum means the
+/// file is corrupt.
+fn replay(file: &mut File, index: &mut BTreeMap<(Table, Vec<u8>), Location>) -> io::Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+    let mut len = 0;
+    let corrupt = |offset: u64| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("corrupt record at offset {} of the chain log", offset),
+        )
+    };
+    loop {
+        let mut header = [0; HEADER_LEN as usize];
+        if read_full(&mut reader, &mut header)? < header.len() {
+            return Ok(len);
+        }
+        if checksum(&header[..4]) != header[4..8] {
+            return Err(corrupt(len));
+        }
+        let payload_len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        // Read through `take` so that a large length cannot allocate more than the rest of
+        // the file.
+        let mut payload = Vec::new();
+        (&mut reader)
+            .take(payload_len as u64)
+            .read_to_end(&mut payload)?;
+        if payload.len() < payload_len as usize {
+            return Ok(len);
+        }
+        if checksum(&payload) != header[8..] || parse(&payload).is_none() {
+            return Err(corrupt(len));
+        }
+        apply(&payload, len + HEADER_LEN, index);
+        len += HEADER_LEN + payload_len as u64;
+    }
+}
+
+/// Reads until `buf` is full or the end of the input, returning the number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn batch(writes: &[(&str, Option<&[u8]>)]) -> WriteBatch {
+        let mut batch = WriteBatch::new();
+        for (key, value) in writes {
+            match value {
+                Some(value) => batch.put(Table::Meta, key, value.to_vec()),
+                None => batch.remove(Table::Meta, key),
+            }
+        }
+        batch
+    }
+
+    #[test]
+    fn test_flat_file_store_replays_log() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        {
+            let store = FlatFileStore::open(&path).unwrap();
+            store
+                .commit(batch(&[("a", Some(b"1")), ("b", Some(b"2"))]))
+                .unwrap();
+            store
+                .commit(batch(&[("a", Some(b"3")), ("b", None), ("c", Some(b""))]))
+                .unwrap();
+            assert_eq!(store.get(Table::Meta, b"a").unwrap(), Some(b"3".to_vec()));
+        }
+
+        let store = FlatFileStore::open(&path).unwrap();
+        assert_eq!(store.get(Table::Meta, b"a").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(store.get(Table::Meta, b"b").unwrap(), None);
+        assert!(store.contains(Table::Meta, b"c").unwrap());
+        assert!(!store.contains(Table::Blocks, b"a").unwrap());
+        assert_eq!(
+            store.range(Table::Meta, b"b").unwrap(),
+            vec![(b"c".to_vec(), Vec::new())]
+        );
+    }
+
+    #[test]
+    fn test_flat_file_store_truncates_torn_record() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        let intact = {
+            let store = FlatFileStore::open(&path).unwrap();
+            store.commit(batch(&[("a", Some(b"1"))])).unwrap();
+            let intact = std::fs::metadata(&path).unwrap().len();
+            store.commit(batch(&[("a", Some(b"2"))])).unwrap();
+            intact
+        };
+        let full = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(full - 1).unwrap();
+        drop(file);
+
+        let store = FlatFileStore::open(&path).unwrap();
+        assert_eq!(store.get(Table::Meta, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), intact);
+        store.commit(batch(&[("b", Some(b"3"))])).unwrap();
+        drop(store);
+
+        let store = FlatFileStore::open(&path).unwrap();
+        assert_eq!(store.get(Table::Meta, b"b").unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_flat_file_store_refuses_corrupt_record_before_tail() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        {
+            let store = FlatFileStore::open(&path).unwrap();
+            store.commit(batch(&[("a", Some(b"1"))])).unwrap();
+            store.commit(batch(&[("b", Some(b"2"))])).unwrap();
+        }
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[HEADER_LEN as usize] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        assert!(matches!(FlatFileStore::open(&path), Err(Error::Io(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_flat_file_store_refuses_corrupt_length() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chain.log");
+        let middle = {
+            let store = FlatFileStore::open(&path).unwrap();
+            store.commit(batch(&[("a", Some(b"1"))])).unwrap();
+            let middle = std::fs::metadata(&path).unwrap().len() as usize;
+            store.commit(batch(&[("b", Some(b"2"))])).unwrap();
+            store.commit(batch(&[("c", Some(b"3"))])).unwrap();
+            middle
+        };
+        let mut bytes = std::fs::read(&path).unwrap();
+        // A length pointing past the end of the file must not pass for a torn tail.
+        bytes[middle + 3] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        assert!(matches!(FlatFileStore::open(&path), Err(Error::Io(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    }
+}
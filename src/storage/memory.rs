@@ -0,0 +1,84 @@
+use super::store::{ChainStore, Entry, Table, Write, WriteBatch};
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Entries = BTreeMap<(Table, Vec<u8>), Vec<u8>>;
+
+/// A `ChainStore` that keeps everything in memory, for tests and simulations.
+///
+/// Clones share the same entries, so a store handed to one `BlockchainManager` can be
+/// reopened by another after the first is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.entries().get(&(table, key.to_vec())).cloned())
+    }
+
+    fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error> {
+        Ok(self
+            .entries()
+            .range((table, start.to_vec())..)
+            .take_while(|((entry_table, _), _)| *entry_table == table)
+            .map(|((_, key), value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+        // Applying a write cannot fail, so holding the lock makes the batch atomic.
+        let mut entries = self.entries();
+        for write in batch.writes() {
+            match write {
+                Write::Put { table, key, value } => {
+                    entries.insert((*table, key.clone()), value.clone());
+                }
+                Write::Remove { table, key } => {
+                    entries.remove(&(*table, key.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_store_commit_and_range() {
+        let store = MemoryStore::new();
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Heights, [0, 2], vec![2]);
+        batch.put(Table::Heights, [0, 1], vec![1]);
+        batch.put(Table::Meta, "tip", vec![9]);
+        batch.remove(Table::Meta, "missing");
+        store.commit(batch).unwrap();
+
+        let reopened = store.clone();
+        assert_eq!(reopened.get(Table::Meta, b"tip").unwrap(), Some(vec![9]));
+        assert_eq!(reopened.get(Table::Blocks, b"tip").unwrap(), None);
+        assert_eq!(
+            reopened.range(Table::Heights, &[0, 2]).unwrap(),
+            vec![(vec![0, 2], vec![2])]
+        );
+        assert_eq!(reopened.range(Table::Heights, &[]).unwrap().len(), 2);
+        assert!(reopened.range(Table::Utxo, &[]).unwrap().is_empty());
+    }
+}
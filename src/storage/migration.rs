@@ -0,0 +1,596 @@
+use super::blockchain_manager::{
+    BLOCKCHAIN_KEY, QUARANTINE_PREFIX, decode, timestamped_key, write_chain,
+};
+use super::memory::MemoryStore;
+use super::store::{ChainStore, Table, WriteBatch};
+use crate::core::blockchain::Blockchain;
+use crate::error::Error;
+use bincode::{deserialize, serialize};
+
+pub(super) const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// Version of the layout written by `BlockchainManager::save`.
+pub const SCHEMA_VERSION: u32 = 1;
+/// Prefix of the keys of `Table::Default` that hold the backups taken by `migrate`.
+pub const BACKUP_PREFIX: &str = "backup/schema-v";
+
+/// A step that upgrades a store from schema version `from` to `from + 1`.
+///
+/// `apply` reads the store and returns the writes of the upgrade. The new version is added
+/// to the same batch, so a crash leaves the store at either version.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&dyn ChainStore) -> Result<WriteBatch, Error>,
+}
+
+/// Every migration step, in order of `from`; the last one upgrades to `SCHEMA_VERSION`.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "split the single-key chain into per-block entries",
+    apply: split_single_key,
+}];
+
+/// What `migrate` did, or would do in a dry run.
+///
+/// - `from`: the schema version the store was at, or `None` if it holds no chain.
+/// - `steps`: the descriptions of the steps that ran, in order.
+/// - `backup_key`: the key of `Table::Default` holding the backup taken before the first
+///   step; `None` in a dry run or when no step ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: Option<u32>,
+    pub steps: Vec<&'static str>,
+    pub backup_key: Option<String>,
+}
+
+/// A key, its table and its value.
+type Snapshot = Vec<(Table, Vec<u8>, Vec<u8>)>;
+
+/// Returns the schema version of `store`: the stored version, `0` for a database that only
+/// holds a single-key chain, or `None` if it holds no chain.
+pub fn schema_version(store: &(impl ChainStore + ?Sized)) -> Result<Option<u32>, Error> {
+    let key = SCHEMA_VERSION_KEY.as_bytes();
+    match store.get(Table::Meta, key)? {
+        Some(data) => Ok(Some(decode(store, Table::Meta, key, data)?)),
+        None if store.contains(Table::Default, BLOCKCHAIN_KEY.as_bytes())? => Ok(Some(0)),
+        None => Ok(None),
+    }
+}
+
+/// Upgrades `store` to `SCHEMA_VERSION` by running every step from its current version.
+///
+/// Before the first step, every entry of the store is copied to a backup key of
+/// `Table::Default` that `restore` can put back. Each step is then committed on its own, so
+/// an interrupted migration resumes from the last completed step. With `dry_run` set, the
+/// steps run on an in-memory copy and the store is left untouched, which shows whether the
+/// migration would succeed.
+///
+/// Fails with `Error::UnsupportedSchema` if the store is newer than this version, and
+/// with the error of the first step that fails, such as `Error::Undecodable`.
+pub fn migrate(store: &impl ChainStore, dry_run: bool) -> Result<MigrationReport, Error> {
+    let from = schema_version(store)?;
+    let mut report = MigrationReport {
+        from,
+        steps: Vec::new(),
+        backup_key: None,
+    };
+    let Some(from) = from else {
+        return Ok(report);
+    };
+    if from > SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchema {
+            found: from,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    let steps: Vec<_> = MIGRATIONS.iter().filter(|step| step.from >= from).collect();
+    if steps.is_empty() {
+        return Ok(report);
+    }
+
+    if dry_run {
+        let copy = MemoryStore::new();
+        let mut batch = WriteBatch::new();
+        for (table, key, value) in snapshot(store)? {
+            batch.put(table, key, value);
+        }
+        copy.commit(batch)?;
+        run(&copy, &steps)?;
+    } else {
+        let backup_key = timestamped_key(&format!("{}{}/", BACKUP_PREFIX, from));
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Default, &backup_key, serialize(&snapshot(store)?)?);
+        store.commit(batch)?;
+        report.backup_key = Some(backup_key);
+        run(store, &steps)?;
+    }
+    report.steps = steps.iter().map(|step| step.description).collect();
+    Ok(report)
+}
+
+/// Puts `store` back to the backup taken by `migrate` under `backup_key`.
+///
+/// Every entry written since is removed, except backups and quarantined bytes. Returns
+/// `false` if there is no backup under `backup_key`.
+pub fn restore(store: &impl ChainStore, backup_key: &str) -> Result<bool, Error> {
+    let Some(data) = store.get(Table::Default, backup_key.as_bytes())? else {
+        return Ok(false);
+    };
+    let backup: Snapshot = deserialize(&data)?;
+    let mut batch = WriteBatch::new();
+    for (table, key, _) in snapshot(store)? {
+        if !(table == Table::Default && key.starts_with(QUARANTINE_PREFIX.as_bytes())) {
+            batch.remove(table, key);
+        }
+    }
+    for (table, key, value) in backup {
+        batch.put(table, key, value);
+    }
+    store.commit(batch)?;
+    Ok(true)
+}
+
+/// Every entry of `store` except the backups.
+fn snapshot(store: &impl ChainStore) -> Result<Snapshot, Error> {
+    let mut entries = Vec::new();
+    for table in Table::ALL {
+        for (key, value) in store.range(table, &[])? {
+            if !(table == Table::Default && key.starts_with(BACKUP_PREFIX.as_bytes())) {
+                entries.push((table, key, value));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn run(store: &dyn ChainStore, steps: &[&Migration]) -> Result<(), Error> {
+    for step in steps {
+        let mut batch = (step.apply)(store)?;
+        batch.put(
+            Table::Meta,
+            SCHEMA_VERSION_KEY,
+            serialize(&(step.from + 1))?,
+        );
+        store.commit(batch)?;
+    }
+    Ok(())
+}
+
+/// Version 0 to 1: the chain stored whole under `"blockchain"` is written as one entry per
+/// block with the height index, the tip and the chain parameters, and the single key is
+/// removed. Chains written before block hashes were `Hash256` are read as well. The saved
+/// ledger state is kept; if it does not match the chain it is rebuilt when the chain is
+/// opened.
+///
+/// Chains of the first release, whose transactions were plain strings, are checked under
+/// the rules they were mined with and re-sealed in the current format, see
+/// `baseline::Blockchain::upgrade`. If they break those rules the step fails with
+/// `Error::LegacyChain` and the single key is left in place.
+///
+/// The entries are written by `write_chain`, which writes the current layout. A later
+/// version that changes that layout must have this step write version 1 on its own.
+fn split_single_key(store: &dyn ChainStore) -> Result<WriteBatch, Error> {
+    let key = BLOCKCHAIN_KEY.as_bytes();
+    let data = store.get(Table::Default, key)?.ok_or(Error::MissingChain)?;
+    let chain = match deserialize::<Blockchain>(&data) {
+        Ok(chain) => chain,
+        Err(_) => match deserialize::<pre_hash256::Blockchain>(&data)
+            .ok()
+            .and_then(pre_hash256::Blockchain::upgrade)
+        {
+            Some(chain) => chain,
+            None => match deserialize::<baseline::Blockchain>(&data) {
+                Ok(chain) => chain.upgrade()?,
+                Err(_) => decode(store, Table::Default, key, data)?,
+            },
+        },
+    };
+    let mut batch = WriteBatch::new();
+    write_chain(store, &chain, chain.side_blocks(), &mut batch)?;
+    Ok(batch)
+}
+
+/// The single-key encoding of chains written before block hashes were `Hash256`, when every
+/// hash was a length-prefixed byte vector.
+mod pre_hash256 {
+    use crate::core::block;
+    use crate::core::block_header::BlockHeader;
+    use crate::core::blockchain;
+    use crate::core::consensus::Consensus;
+    use crate::core::reward::RewardSchedule;
+    use crate::core::transaction::Transaction;
+    use crate::core::utxo::LedgerMode;
+    use crate::utils::hash::Hash256;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Header {
+        pub(super) version: u32,
+        pub(super) timestamp: u64,
+        pub(super) prev_hash: Vec<u8>,
+        pub(super) merkle_root: Vec<u8>,
+        pub(super) nonce: u64,
+        pub(super) bits: u32,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Block {
+        pub(super) header: Header,
+        pub(super) transactions: Vec<Transaction>,
+        pub(super) hash: Vec<u8>,
+        pub(super) seal: Vec<u8>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Blockchain {
+        pub(super) chain: Vec<Block>,
+        pub(super) side_blocks: BTreeMap<Vec<u8>, Block>,
+        pub(super) difficulty: u32,
+        pub(super) consensus: Consensus,
+        pub(super) ledger: LedgerMode,
+        pub(super) reward: RewardSchedule,
+    }
+
+    impl Block {
+        fn upgrade(self) -> Option<block::Block> {
+            Some(block::Block {
+                header: BlockHeader {
+                    version: self.header.version,
+                    timestamp: self.header.timestamp,
+                    prev_hash: Hash256::from_slice(&self.header.prev_hash).ok()?,
+                    merkle_root: Hash256::from_slice(&self.header.merkle_root).ok()?,
+                    nonce: self.header.nonce,
+                    bits: self.header.bits,
+                },
+                transactions: self.transactions,
+                hash: Hash256::from_slice(&self.hash).ok()?,
+                seal: self.seal,
+            })
+        }
+    }
+
+    impl Blockchain {
+        /// Converts the chain, or returns `None` if a hash is not 32 bytes long.
+        pub(super) fn upgrade(self) -> Option<blockchain::Blockchain> {
+            let chain = self
+                .chain
+                .into_iter()
+                .map(Block::upgrade)
+                .collect::<Option<Vec<_>>>()?;
+            let side_blocks = self
+                .side_blocks
+                .into_values()
+                .map(Block::upgrade)
+                .collect::<Option<Vec<_>>>()?;
+            let mut blockchain = blockchain::Blockchain::from_parts(
+                self.difficulty,
+                self.ledger,
+                self.consensus,
+                chain,
+                side_blocks,
+            );
+            blockchain.reward = self.reward;
+            Some(blockchain)
+        }
+    }
+}
+
+/// The single-key encoding of the first release, whose blocks held their transactions as
+/// strings and a hash over their text.
+mod baseline {
+    use crate::core::block;
+    use crate::core::blockchain;
+    use crate::core::consensus::Consensus;
+    use crate::core::miner::{CancelToken, MiningOutcome};
+    use crate::core::transaction::Transaction;
+    use crate::core::utxo::LedgerMode;
+    use crate::core::validation::{InvalidBlock, ValidationError};
+    use crate::error::Error;
+    use crate::utils::crypto::address_from_public_key;
+    use crate::utils::hash::{Hash256, bytes_to_hex_string};
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Header {
+        pub(super) timestamp: u64,
+        pub(super) prev_hash: Vec<u8>,
+        pub(super) nonce: u64,
+        pub(super) difficulty: u32,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Block {
+        pub(super) header: Header,
+        pub(super) transactions: Vec<String>,
+        pub(super) hash: Vec<u8>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(super) struct Blockchain {
+        pub(super) chain: Vec<Block>,
+        pub(super) difficulty: u32,
+    }
+
+    impl Block {
+        pub(super) fn calculate_hash(&self) -> Vec<u8> {
+            let data = format!(
+                "{}{}{}{}",
+                self.header.timestamp,
+                bytes_to_hex_string(&self.header.prev_hash),
+                self.header.nonce,
+                self.transactions.join("")
+            );
+            Sha256::digest(data.as_bytes()).to_vec()
+        }
+
+        /// Whether the hash starts with `difficulty` zero bits.
+        fn meets_difficulty(&self, difficulty: u32) -> bool {
+            let zeros = match self.hash.iter().position(|&byte| byte != 0) {
+                Some(index) => index * 8 + self.hash[index].leading_zeros() as usize,
+                None => self.hash.len() * 8,
+            };
+            zeros >= difficulty as usize
+        }
+    }
+
+    impl Blockchain {
+        /// Checks the chain under the rules it was mined with, naming the first block that
+        /// breaks them.
+        pub(super) fn verify(&self) -> Result<(), ValidationError> {
+            if self.chain.is_empty() {
+                return Err(ValidationError {
+                    height: 0,
+                    reason: InvalidBlock::MissingGenesis,
+                });
+            }
+            let mut prev_hash = vec![0; 32];
+            for (height, block) in self.chain.iter().enumerate() {
+                let reason = if block.header.prev_hash != prev_hash {
+                    InvalidBlock::BrokenLink
+                } else if block.header.difficulty != self.difficulty {
+                    InvalidBlock::DifficultyMismatch {
+                        expected: self.difficulty,
+                        found: block.header.difficulty,
+                    }
+                } else if block.calculate_hash() != block.hash {
+                    InvalidBlock::HashMismatch
+                } else if !block.meets_difficulty(self.difficulty) {
+                    InvalidBlock::InsufficientWork
+                } else {
+                    prev_hash.clone_from(&block.hash);
+                    continue;
+                };
+                return Err(ValidationError { height, reason });
+            }
+            Ok(())
+        }
+
+        /// Re-seals the chain in the current format, keeping the timestamps and the order of
+        /// the blocks.
+        ///
+        /// Each string becomes a memo transaction. Every block after genesis is given a
+        /// coinbase that pays nothing to the address of the all-zero key, so no balance is
+        /// created. Fails with `Error::LegacyChain` if the chain breaks its own rules or the
+        /// converted chain does not validate.
+        pub(super) fn upgrade(&self) -> Result<blockchain::Blockchain, Error> {
+            self.verify().map_err(Error::LegacyChain)?;
+            let mut chain = blockchain::Blockchain::from_parts(
+                self.difficulty,
+                LedgerMode::Account,
+                Consensus::default(),
+                Vec::new(),
+                Vec::new(),
+            );
+            let unclaimed = address_from_public_key(&[0; 32]);
+            for (height, old) in self.chain.iter().enumerate() {
+                let mut transactions = Vec::new();
+                if height > 0 {
+                    transactions.push(Transaction::coinbase(
+                        height,
+                        &unclaimed,
+                        0,
+                        LedgerMode::Account,
+                    )?);
+                }
+                for text in &old.transactions {
+                    transactions.push(Transaction::memo(text.as_str())?);
+                }
+                let prev_hash = chain
+                    .get_last_block()
+                    .map_or(Hash256::ZERO, |block| block.hash);
+                let mut unsealed = block::Block::new(prev_hash, transactions, chain.next_bits())?;
+                unsealed.header.timestamp = old.header.timestamp;
+                let MiningOutcome::Mined { block, .. } =
+                    chain.seal(&unsealed, &CancelToken::new(), |_| {})?
+                else {
+                    unreachable!("mining is never cancelled");
+                };
+                chain.chain.push(block);
+            }
+            match chain.validate().and_then(|()| chain.rebuild_state()) {
+                Ok(()) => Ok(chain),
+                Err(Error::Validation(err)) => Err(Error::LegacyChain(err)),
+                Err(err) => Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::Transaction;
+    use crate::core::validation::InvalidBlock;
+    use crate::storage::blockchain_manager::{BlockchainManager, OpenOptions};
+    use crate::utils::hash::hex_string_to_bytes;
+
+    const MINER: &str = "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450";
+    /// `Blockchain::new(4)` of the first release with one block holding `"alice pays bob 5"`
+    /// and `"hello"`, as written by its `save`.
+    const BASELINE_CHAIN: &str = concat!(
+        "0200000000000000bcc6d26a00000000200000000000000000000000000000000000000000000000",
+        "00000000000000000000000000000000080000000000000004000000010000000000000007000000",
+        "0000000067656e657369732000000000000000060a1b2cd0d3360fd8a06510c51c8c967ec1480ddb",
+        "2d5aaf963662125efaea38bcc6d26a000000002000000000000000060a1b2cd0d3360fd8a06510c5",
+        "1c8c967ec1480ddb2d5aaf963662125efaea38080000000000000004000000020000000000000010",
+        "00000000000000616c696365207061797320626f622035050000000000000068656c6c6f20000000",
+        "0000000009b79b6de71b66a36795ad5b753a98bfaed9c6f4a9aa2a19405e79c2e703930804000000",
+    );
+
+    /// Encodes `chain` as a single-key chain from before `Hash256`.
+    fn pre_hash256_bytes(chain: &Blockchain) -> Vec<u8> {
+        let block = |block: &crate::core::block::Block| pre_hash256::Block {
+            header: pre_hash256::Header {
+                version: block.header.version,
+                timestamp: block.header.timestamp,
+                prev_hash: block.header.prev_hash.as_bytes().to_vec(),
+                merkle_root: block.header.merkle_root.as_bytes().to_vec(),
+                nonce: block.header.nonce,
+                bits: block.header.bits,
+            },
+            transactions: block.transactions.clone(),
+            hash: block.hash.as_bytes().to_vec(),
+            seal: block.seal.clone(),
+        };
+        serialize(&pre_hash256::Blockchain {
+            chain: chain.chain.iter().map(block).collect(),
+            side_blocks: Default::default(),
+            difficulty: chain.difficulty,
+            consensus: chain.consensus.clone(),
+            ledger: chain.ledger,
+            reward: chain.reward,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_migrates_pre_hash256_chain_with_backup() {
+        let mut chain = Blockchain::new(1).unwrap();
+        chain
+            .add_block(MINER, vec![Transaction::memo("block 1").unwrap()])
+            .unwrap();
+        let legacy = pre_hash256_bytes(&chain);
+        let store = MemoryStore::new();
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Default, BLOCKCHAIN_KEY, legacy.clone());
+        store.commit(batch).unwrap();
+        assert_eq!(schema_version(&store).unwrap(), Some(0));
+
+        let dry_run = migrate(&store, true).unwrap();
+        assert_eq!(dry_run.from, Some(0));
+        assert_eq!(dry_run.steps, vec![MIGRATIONS[0].description]);
+        assert_eq!(dry_run.backup_key, None);
+        assert_eq!(schema_version(&store).unwrap(), Some(0));
+        assert_eq!(store.range(Table::Blocks, &[]).unwrap(), Vec::new());
+
+        let report = migrate(&store, false).unwrap();
+        assert_eq!(report.steps, dry_run.steps);
+        assert_eq!(schema_version(&store).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(migrate(&store, false).unwrap().steps, Vec::<&str>::new());
+        {
+            let manager =
+                BlockchainManager::with_store(store.clone(), OpenOptions::default()).unwrap();
+            let hashes = |chain: &Blockchain| chain.iter().map(|b| b.hash).collect::<Vec<_>>();
+            assert_eq!(hashes(manager.blockchain()), hashes(&chain));
+            assert_eq!(
+                manager.blockchain().balance_of(MINER),
+                chain.balance_of(MINER)
+            );
+        }
+
+        let backup_key = report.backup_key.unwrap();
+        assert!(restore(&store, &backup_key).unwrap());
+        assert_eq!(schema_version(&store).unwrap(), Some(0));
+        let restored = store.get(Table::Default, BLOCKCHAIN_KEY.as_bytes());
+        assert_eq!(restored.unwrap(), Some(legacy));
+        assert_eq!(store.range(Table::Blocks, &[]).unwrap(), Vec::new());
+        assert!(!restore(&store, "missing").unwrap());
+    }
+
+    fn single_key_store(data: Vec<u8>) -> MemoryStore {
+        let store = MemoryStore::new();
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Default, BLOCKCHAIN_KEY, data);
+        store.commit(batch).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_migrates_baseline_chain() {
+        let data = hex_string_to_bytes(BASELINE_CHAIN).unwrap();
+        let legacy: baseline::Blockchain = deserialize(&data).unwrap();
+        assert_eq!(legacy.chain[1].hash, legacy.chain[1].calculate_hash());
+        let store = single_key_store(data);
+
+        migrate(&store, false).unwrap();
+        let manager = BlockchainManager::with_store(store, OpenOptions::default()).unwrap();
+        let chain = manager.blockchain();
+        assert_eq!(chain.chain.len(), 2);
+        for (block, old) in chain.iter().zip(&legacy.chain) {
+            assert_eq!(block.header.timestamp, old.header.timestamp);
+            let memos: Vec<_> = block
+                .transactions
+                .iter()
+                .filter(|tx| !tx.is_coinbase())
+                .map(|tx| tx.memo.as_str())
+                .collect();
+            assert_eq!(memos, old.transactions);
+        }
+        assert!(chain.chain[1].transactions[0].is_coinbase());
+    }
+
+    #[test]
+    fn test_refuses_tampered_baseline_chain() {
+        let data = hex_string_to_bytes(BASELINE_CHAIN).unwrap();
+        let mut legacy: baseline::Blockchain = deserialize(&data).unwrap();
+        legacy.chain[1].transactions[1] = "hellO".to_string();
+        let tampered = serialize(&legacy).unwrap();
+        let store = single_key_store(tampered.clone());
+
+        assert!(matches!(
+            migrate(&store, true),
+            Err(Error::LegacyChain(err))
+                if err.height == 1 && err.reason == InvalidBlock::HashMismatch
+        ));
+        assert!(matches!(migrate(&store, false), Err(Error::LegacyChain(_))));
+        let options = OpenOptions {
+            repair: true,
+            ..OpenOptions::default()
+        };
+        assert!(matches!(
+            BlockchainManager::with_store(store.clone(), options),
+            Err(Error::LegacyChain(_))
+        ));
+        let kept = store.get(Table::Default, BLOCKCHAIN_KEY.as_bytes());
+        assert_eq!(kept.unwrap(), Some(tampered));
+        assert_eq!(schema_version(&store).unwrap(), Some(0));
+        assert_eq!(store.range(Table::Blocks, &[]).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        for (version, step) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(step.from, version as u32);
+        }
+        assert_eq!(MIGRATIONS.len() as u32, SCHEMA_VERSION);
+
+        let store = MemoryStore::new();
+        assert_eq!(migrate(&store, false).unwrap().from, None);
+        let mut batch = WriteBatch::new();
+        batch.put(
+            Table::Meta,
+            SCHEMA_VERSION_KEY,
+            serialize(&(SCHEMA_VERSION + 1)).unwrap(),
+        );
+        store.commit(batch).unwrap();
+        assert!(matches!(
+            migrate(&store, true),
+            Err(Error::UnsupportedSchema { found, supported: SCHEMA_VERSION })
+                if found == SCHEMA_VERSION + 1
+        ));
+        assert!(BlockchainManager::with_store(store, OpenOptions::default()).is_err());
+    }
+}
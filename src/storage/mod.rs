@@ -0,0 +1,7 @@
+pub mod archive;
+pub mod blockchain_manager;
+pub mod flat_file;
+pub mod memory;
+pub mod migration;
+pub mod sled_store;
+pub mod store;
@@ -0,0 +1,147 @@
+use super::store::{ChainStore, Entry, Table, Write, WriteBatch};
+use crate::error::Error;
+use sled::transaction::{ConflictableTransactionError, TransactionError, TransactionalTree};
+use sled::{Db, Transactional, Tree};
+use std::path::Path;
+
+const BLOCKS_TREE: &str = "blocks";
+const HEIGHTS_TREE: &str = "heights";
+const META_TREE: &str = "meta";
+const UTXO_TREE: &str = "utxo";
+const ACCOUNTS_TREE: &str = "accounts";
+
+/// A `ChainStore` backed by a sled database, one sled tree per `Table`.
+///
+/// `Table::Default` is the default tree of the database, which also holds the single-key
+/// chain of databases written before the per-block layout.
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    db: Db,
+    blocks: Tree,
+    heights: Tree,
+    meta: Tree,
+    utxo: Tree,
+    accounts: Tree,
+}
+
+impl SledStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    pub fn from_db(db: Db) -> Result<Self, Error> {
+        Ok(Self {
+            blocks: db.open_tree(BLOCKS_TREE)?,
+            heights: db.open_tree(HEIGHTS_TREE)?,
+            meta: db.open_tree(META_TREE)?,
+            utxo: db.open_tree(UTXO_TREE)?,
+            accounts: db.open_tree(ACCOUNTS_TREE)?,
+            db,
+        })
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    fn tree(&self, table: Table) -> &Tree {
+        match table {
+            Table::Default => &self.db,
+            Table::Blocks => &self.blocks,
+            Table::Heights => &self.heights,
+            Table::Meta => &self.meta,
+            Table::Utxo => &self.utxo,
+            Table::Accounts => &self.accounts,
+        }
+    }
+}
+
+impl ChainStore for SledStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.tree(table).get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        for entry in self.tree(table).range(start..) {
+            let (key, value) = entry?;
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    /// Applies the batch in a single sled transaction across the trees, then flushes the
+    /// database so that the batch is on disk when `commit` returns.
+    fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+        (
+            &*self.db,
+            &self.blocks,
+            &self.heights,
+            &self.meta,
+            &self.utxo,
+            &self.accounts,
+        )
+            .transaction(|(default, blocks, heights, meta, utxo, accounts)| {
+                let tree = |table: Table| -> &TransactionalTree {
+                    match table {
+                        Table::Default => default,
+                        Table::Blocks => blocks,
+                        Table::Heights => heights,
+                        Table::Meta => meta,
+                        Table::Utxo => utxo,
+                        Table::Accounts => accounts,
+                    }
+                };
+                for write in batch.writes() {
+                    match write {
+                        Write::Put { table, key, value } => {
+                            tree(*table).insert(key.as_slice(), value.as_slice())?;
+                        }
+                        Write::Remove { table, key } => {
+                            tree(*table).remove(key.as_slice())?;
+                        }
+                    }
+                }
+                Ok::<(), ConflictableTransactionError>(())
+            })
+            .map_err(|err| match err {
+                TransactionError::Storage(err) | TransactionError::Abort(err) => err,
+            })?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn contains(&self, table: Table, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.tree(table).contains_key(key)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sled_store_commits_across_trees() {
+        let temp_dir = tempdir().unwrap();
+        let store = SledStore::open(temp_dir.path()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(Table::Default, "state", vec![1]);
+        batch.put(Table::Blocks, [7; 32], vec![2]);
+        batch.put(Table::Heights, 0u64.to_be_bytes(), [7; 32]);
+        store.commit(batch).unwrap();
+
+        assert_eq!(store.db().get("state").unwrap().unwrap().to_vec(), vec![1]);
+        assert!(store.contains(Table::Blocks, &[7; 32]).unwrap());
+        assert!(!store.contains(Table::Meta, &[7; 32]).unwrap());
+        assert_eq!(
+            store.range(Table::Heights, &[]).unwrap(),
+            vec![(0u64.to_be_bytes().to_vec(), vec![7; 32])]
+        );
+
+        let mut batch = WriteBatch::new();
+        batch.remove(Table::Blocks, [7; 32]);
+        store.commit(batch).unwrap();
+        assert_eq!(store.get(Table::Blocks, &[7; 32]).unwrap(), None);
+    }
+}
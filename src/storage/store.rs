@@ -0,0 +1,108 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// A named collection of keys in a `ChainStore`.
+///
+/// - `Default`: the tips of the ledger state and of the UTXO set, quarantined bytes and the
+///   backups taken before migrations.
+/// - `Blocks`: every stored block, keyed by its hash.
+/// - `Heights`: the hash of the active block at each height, keyed by the big-endian height.
+/// - `Meta`: the tip, the chain parameters and the schema version.
+/// - `Utxo`: one entry per unspent output, keyed by `OutPoint::to_key`.
+/// - `Accounts`: the balance and nonce of every account of an account chain, keyed by address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Table {
+    Default,
+    Blocks,
+    Heights,
+    Meta,
+    Utxo,
+    Accounts,
+}
+
+impl Table {
+    pub const ALL: [Table; 6] = [
+        Table::Default,
+        Table::Blocks,
+        Table::Heights,
+        Table::Meta,
+        Table::Utxo,
+        Table::Accounts,
+    ];
+}
+
+/// A key and its value.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// A single change in a `WriteBatch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Write {
+    Put {
+        table: Table,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Remove {
+        table: Table,
+        key: Vec<u8>,
+    },
+}
+
+/// Changes that a `ChainStore` applies together or not at all, in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    writes: Vec<Write>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, table: Table, key: impl AsRef<[u8]>, value: impl Into<Vec<u8>>) {
+        self.writes.push(Write::Put {
+            table,
+            key: key.as_ref().to_vec(),
+            value: value.into(),
+        });
+    }
+
+    pub fn remove(&mut self, table: Table, key: impl AsRef<[u8]>) {
+        self.writes.push(Write::Remove {
+            table,
+            key: key.as_ref().to_vec(),
+        });
+    }
+
+    pub fn writes(&self) -> &[Write] {
+        &self.writes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// The storage a `BlockchainManager` keeps its blocks, indexes and ledger state in.
+///
+/// - `get`: returns the value stored under `key` in `table`.
+/// - `range`: returns the entries of `table` whose key is at least `start`, in key order.
+/// - `commit`: applies every write of `batch` or, if it fails, none of them. When it returns
+///   `Ok` the batch is durable, and a crash at any point leaves either all of the batch or
+///   none of it in the store.
+/// - `contains`: whether `table` has a value under `key`; stores that can answer without
+///   reading the value should override it.
+///
+/// Keys compare as byte strings. The manager decides what is stored under which key, so a
+/// store only has to keep bytes.
+pub trait ChainStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    fn range(&self, table: Table, start: &[u8]) -> Result<Vec<Entry>, Error>;
+
+    fn commit(&self, batch: WriteBatch) -> Result<(), Error>;
+
+    fn contains(&self, table: Table, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.get(table, key)?.is_some())
+    }
+}
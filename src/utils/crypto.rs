@@ -0,0 +1,240 @@
+use super::hash::{HexError, bytes_to_hex_string, hex_string_to_bytes};
+use crate::error::Error;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+const ADDRESS_VERSION: u8 = 0x00;
+const ADDRESS_HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+
+/// An Ed25519 keypair used to sign transactions.
+///
+/// # Methods
+///
+/// - `generate() -> Self`: Creates a keypair from 32 random bytes.
+///
+/// - `from_secret_bytes(secret: &[u8; 32]) -> Self`: Restores a keypair from its secret key.
+///
+/// - `load_or_create(path: impl AsRef<Path>) -> Result<Self, Error>`: Reads the hex-encoded
+///   secret key stored at `path`, or generates a keypair and stores its secret key there if
+///   the file does not exist. On Unix the new file is only readable by its owner.
+///
+/// - `public_key(&self) -> Vec<u8>`: Returns the 32-byte public key.
+///
+/// - `address(&self) -> String`: Returns the checksummed address of the public key.
+///
+/// - `sign(&self, message: &[u8]) -> Vec<u8>`: Returns the 64-byte signature of `message`.
+pub struct Keypair {
+    signing_key: SigningKey,
+}
+
+impl Keypair {
+    pub fn generate() -> Self {
+        Self::from_secret_bytes(&rand::random())
+    }
+
+    pub fn from_secret_bytes(secret: &[u8; 32]) -> Self {
+        Self {
+            signing_key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn load_or_create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(hex) => {
+                let bytes = hex_string_to_bytes(hex.trim())?;
+                let secret: [u8; 32] =
+                    bytes
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| HexError::WrongLength {
+                            expected: 32,
+                            found: bytes.len(),
+                        })?;
+                Ok(Self::from_secret_bytes(&secret))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let keypair = Self::generate();
+                let mut options = OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                let mut file = options.open(path)?;
+                writeln!(file, "{}", bytes_to_hex_string(&keypair.secret_bytes()))?;
+                file.sync_all()?;
+                Ok(keypair)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.signing_key.to_bytes()
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        self.signing_key.verifying_key().to_bytes().to_vec()
+    }
+
+    pub fn address(&self) -> String {
+        address_from_public_key(&self.public_key())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+}
+
+/// Shows only the address, so that the secret key never ends up in logs.
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("address", &self.address())
+            .finish_non_exhaustive()
+    }
+}
+
+fn checksum(payload: &[u8]) -> Vec<u8> {
+    let first = Sha256::digest(payload);
+    Sha256::digest(first)[..CHECKSUM_LEN].to_vec()
+}
+
+/// Derives an address from an Ed25519 public key.
+///
+/// The address is the hex encoding of a version byte, the first 20 bytes of the SHA-256 of the
+/// public key and a 4-byte checksum (the first bytes of a double SHA-256 over the preceding
+/// bytes), so that mistyped addresses can be detected with `is_valid_address`.
+pub fn address_from_public_key(public_key: &[u8]) -> String {
+    let mut payload = vec![ADDRESS_VERSION];
+    payload.extend_from_slice(&Sha256::digest(public_key)[..ADDRESS_HASH_LEN]);
+    let checksum = checksum(&payload);
+    payload.extend_from_slice(&checksum);
+    bytes_to_hex_string(&payload)
+}
+
+pub fn is_valid_address(address: &str) -> bool {
+    let expected_len = (1 + ADDRESS_HASH_LEN + CHECKSUM_LEN) * 2;
+    if address.len() != expected_len || !address.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return false;
+    }
+    let Ok(bytes) = hex_string_to_bytes(address) else {
+        return false;
+    };
+    let (payload, check) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    payload[0] == ADDRESS_VERSION && checksum(payload) == check
+}
+
+/// Returns `true` if `signature` is a valid Ed25519 signature of `message` by `public_key`.
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let Ok(public_key) = <[u8; 32]>::try_from(public_key) else {
+        return false;
+    };
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else {
+        return false;
+    };
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return false;
+    };
+    verifying_key.verify(message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert_eq!(signature.len(), 64);
+        assert!(verify_signature(
+            &keypair.public_key(),
+            b"message",
+            &signature
+        ));
+        assert!(!verify_signature(
+            &keypair.public_key(),
+            b"other",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_other_key_and_malformed_input() {
+        let keypair = Keypair::generate();
+        let other = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(!verify_signature(
+            &other.public_key(),
+            b"message",
+            &signature
+        ));
+        assert!(!verify_signature(&[1, 2, 3], b"message", &signature));
+        assert!(!verify_signature(
+            &keypair.public_key(),
+            b"message",
+            &signature[..63]
+        ));
+    }
+
+    #[test]
+    fn test_keypair_roundtrip() {
+        let keypair = Keypair::generate();
+        let restored = Keypair::from_secret_bytes(&keypair.secret_bytes());
+
+        assert_eq!(keypair.public_key(), restored.public_key());
+        assert_eq!(keypair.address(), restored.address());
+    }
+
+    #[test]
+    fn test_keypair_load_or_create() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("node.key");
+        let created = Keypair::load_or_create(&path).unwrap();
+        let loaded = Keypair::load_or_create(&path).unwrap();
+        assert_eq!(created.address(), loaded.address());
+
+        fs::write(&path, "abcd").unwrap();
+        assert!(matches!(
+            Keypair::load_or_create(&path),
+            Err(Error::Hex(HexError::WrongLength {
+                expected: 32,
+                found: 2
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_address_checksum() {
+        let address = Keypair::generate().address();
+        assert_eq!(address.len(), 50);
+        assert!(is_valid_address(&address));
+
+        let mut corrupted: Vec<char> = address.chars().collect();
+        corrupted[10] = if corrupted[10] == '0' { '1' } else { '0' };
+        let corrupted: String = corrupted.into_iter().collect();
+        assert!(!is_valid_address(&corrupted));
+        assert!(!is_valid_address("alice"));
+    }
+
+    #[test]
+    fn test_address_vector() {
+        let keypair = Keypair::from_secret_bytes(&[7u8; 32]);
+        assert_eq!(
+            bytes_to_hex_string(&keypair.public_key()),
+            "ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c"
+        );
+        assert_eq!(
+            keypair.address(),
+            "00fe812c12f3ab4ce6ac5db69ac352f906cb1b11efe72c9450"
+        );
+    }
+}
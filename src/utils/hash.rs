@@ -0,0 +1,209 @@
+use crate::error::Error;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The reason a string could not be decoded by `hex_string_to_bytes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// The string has an odd number of characters.
+    OddLength(usize),
+    /// The character at byte offset `index` is not a hexadecimal digit.
+    InvalidDigit { index: usize },
+    /// The string decodes to `found` bytes where `expected` were required.
+    WrongLength { expected: usize, found: usize },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::OddLength(len) => write!(f, "odd number of digits ({})", len),
+            HexError::InvalidDigit { index } => {
+                write!(f, "character at {} is not a hex digit", index)
+            }
+            HexError::WrongLength { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut acc, b| {
+        use std::fmt::Write;
+        write!(&mut acc, "{:02x}", b).unwrap();
+        acc
+    })
+}
+
+pub fn hex_string_to_bytes(hex: &str) -> Result<Vec<u8>, Error> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(HexError::OddLength(hex.len()).into());
+    }
+    if let Some(index) = hex.bytes().position(|b| !b.is_ascii_hexdigit()) {
+        return Err(HexError::InvalidDigit { index }.into());
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked hex digits"))
+        .collect())
+}
+
+/// A 32-byte SHA-256 digest, such as a block hash.
+///
+/// It displays and parses as 64 lowercase hex digits. Human-readable serde formats store that
+/// hex string; binary formats such as bincode store the 32 raw bytes without a length prefix.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash256(pub [u8; 32]);
+
+impl Hash256 {
+    pub const LEN: usize = 32;
+    /// The all-zero hash, which the genesis block uses as its previous hash.
+    pub const ZERO: Self = Self([0; Self::LEN]);
+
+    /// Returns the hash held in `bytes`, or `HexError::WrongLength` unless it is 32 bytes.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        <[u8; Self::LEN]>::try_from(bytes).map(Self).map_err(|_| {
+            HexError::WrongLength {
+                expected: Self::LEN,
+                found: bytes.len(),
+            }
+            .into()
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8; Self::LEN] {
+        &self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+impl From<[u8; 32]> for Hash256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Hash256 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bytes_to_hex_string(&self.0))
+    }
+}
+
+impl fmt::Debug for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash256({})", self)
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        Self::from_slice(&hex_string_to_bytes(hex)?)
+    }
+}
+
+impl Serialize for Hash256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(HexVisitor)
+        } else {
+            <[u8; Self::LEN]>::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
+struct HexVisitor;
+
+impl Visitor<'_> for HexVisitor {
+    type Value = Hash256;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} hex digits", Hash256::LEN * 2)
+    }
+
+    fn visit_str<E: de::Error>(self, hex: &str) -> Result<Hash256, E> {
+        hex.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "00000000000000000000000000000000000000000000000000000000000000ff";
+
+    #[test]
+    fn test_hex_errors() {
+        assert!(matches!(
+            hex_string_to_bytes("abc"),
+            Err(Error::Hex(HexError::OddLength(3)))
+        ));
+        assert!(matches!(
+            hex_string_to_bytes("0g"),
+            Err(Error::Hex(HexError::InvalidDigit { index: 1 }))
+        ));
+        // A multi-byte character is rejected instead of splitting a char boundary.
+        assert!(matches!(
+            hex_string_to_bytes("aé0"),
+            Err(Error::Hex(HexError::InvalidDigit { index: 1 }))
+        ));
+        assert!(matches!(
+            hex_string_to_bytes("+f"),
+            Err(Error::Hex(HexError::InvalidDigit { index: 0 }))
+        ));
+    }
+
+    #[test]
+    fn test_hash256_parse_and_display() {
+        let hash: Hash256 = HEX.parse().unwrap();
+        assert_eq!(hash.as_bytes()[31], 0xff);
+        assert_eq!(hash.to_string(), HEX);
+        assert_eq!(HEX.to_uppercase().parse::<Hash256>().unwrap(), hash);
+        assert!(matches!(
+            "00ff".parse::<Hash256>(),
+            Err(Error::Hex(HexError::WrongLength {
+                expected: 32,
+                found: 2
+            }))
+        ));
+        assert!(Hash256::ZERO.is_zero());
+        assert!(Hash256::from_slice(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn test_hash256_serde() {
+        let hash: Hash256 = HEX.parse().unwrap();
+        let bytes = bincode::serialize(&hash).unwrap();
+        assert_eq!(bytes, hash.as_bytes());
+        assert_eq!(bincode::deserialize::<Hash256>(&bytes).unwrap(), hash);
+
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", HEX));
+        assert_eq!(serde_json::from_str::<Hash256>(&json).unwrap(), hash);
+        assert!(serde_json::from_str::<Hash256>("\"00ff\"").is_err());
+    }
+}